@@ -0,0 +1,2951 @@
+//! 待办事项的核心类型和文件读写，命令行界面（main.rs）只负责参数解析和交互。
+
+use chrono::{DateTime, Days, Local, Months, NaiveDate};
+use clap::ValueEnum;
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use owo_colors::{OwoColorize, Stream, Style};
+use property::Property;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::OpenOptions;
+use std::io::prelude::*;
+use std::io::{BufRead, BufReader, Read, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::{fs, io};
+
+// 为 true 时之后打开的列表只在内存中修改，不写回文件（命令行的 --dry-run）
+pub static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+// 写文件期间持有；Ctrl-C 退出前先获取它，保证正在进行的保存能够完成
+pub static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+pub const DEFAULT_BACKUPS: usize = 5;
+
+// 每次保存前保留的备份份数，0 表示不备份（命令行从配置文件读取后设置）
+pub static BACKUPS: AtomicUsize = AtomicUsize::new(DEFAULT_BACKUPS);
+
+// 为 true 时 `.todo` 写成格式化的 JSON 数组而不是每行一项，新增时也整体重写（命令行从配置文件读取后设置）
+pub static PRETTY_JSON: AtomicBool = AtomicBool::new(false);
+
+/// 首次使用时数据目录可能还不存在，打开文件前先创建
+fn ensure_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent),
+        _ => Ok(()),
+    }
+}
+
+// 负数表示暂缓处理的 backlog，view 默认不显示
+const PRIORITY_RANGE: std::ops::RangeInclusive<i16> = -9..=9;
+pub const HIGH_PRIORITY: i16 = 7; // 不低于该值的高亮显示
+const LOW_PRIORITY: i16 = 2; // 不高于该值的淡化显示
+pub const BACKLOG_PRIORITY: i16 = -1;
+
+/// 优先级等级，按紧急程度排序。文件中仍保存 -9 到 9 的整数，
+/// 等级是对数值的分段：负数为 Backlog，不高于 LOW_PRIORITY 为 Low，不低于 HIGH_PRIORITY 为 High，9 为 Critical
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Backlog,
+    Low,
+    Normal,
+    High,
+    Critical,
+}
+
+impl From<i16> for Priority {
+    fn from(priority: i16) -> Self {
+        match priority {
+            p if p < 0 => Priority::Backlog,
+            p if p <= LOW_PRIORITY => Priority::Low,
+            p if p < HIGH_PRIORITY => Priority::Normal,
+            p if p < *PRIORITY_RANGE.end() => Priority::High,
+            _ => Priority::Critical,
+        }
+    }
+}
+
+/// 按名称指定等级时写入文件的数值
+impl From<Priority> for i16 {
+    fn from(priority: Priority) -> Self {
+        match priority {
+            Priority::Backlog => BACKLOG_PRIORITY,
+            Priority::Low => 2,
+            Priority::Normal => 5,
+            Priority::High => 8,
+            Priority::Critical => *PRIORITY_RANGE.end(),
+        }
+    }
+}
+
+/// 等级名称，忽略大小写；medium 是 normal 的旧称
+impl std::str::FromStr for Priority {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "backlog" => Ok(Priority::Backlog),
+            "low" => Ok(Priority::Low),
+            "normal" | "medium" => Ok(Priority::Normal),
+            "high" => Ok(Priority::High),
+            "critical" => Ok(Priority::Critical),
+            _ => Err(()),
+        }
+    }
+}
+
+pub fn is_backlog(priority: i16) -> bool {
+    Priority::from(priority) == Priority::Backlog
+}
+
+/// 解析优先级：接受 -9 到 9 的整数，或 backlog / low / normal（medium）/ high / critical
+pub fn parse_priority(s: &str) -> Result<i16, String> {
+    let priority = match s.parse::<Priority>() {
+        Ok(level) => i16::from(level),
+        Err(()) => s.trim().parse::<i16>().map_err(|_| {
+            format!(
+                "invalid priority '{}', expected -9 to 9 or backlog/low/normal/high/critical",
+                s
+            )
+        })?,
+    };
+    if !PRIORITY_RANGE.contains(&priority) {
+        return Err(format!(
+            "priority {} is out of range, expected {} to {}",
+            priority,
+            PRIORITY_RANGE.start(),
+            PRIORITY_RANGE.end()
+        ));
+    }
+    Ok(priority)
+}
+
+/// 校验链接：去掉首尾空白后须是带协议的完整 URL（如 https://example.com），原样保存
+pub fn parse_url(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    url::Url::parse(s)
+        .map(|_| s.to_string())
+        .map_err(|e| format!("invalid URL '{}': {}", s, e))
+}
+
+/// 校验名称：去掉首尾空白，拒绝空名称以及包含换行等控制字符的名称
+pub fn validate_name(s: &str) -> Result<String, String> {
+    let name = s.trim();
+    if name.is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if name.chars().any(char::is_control) {
+        return Err(format!(
+            "name {:?} contains newlines or other control characters",
+            name
+        ));
+    }
+    Ok(name.to_string())
+}
+
+pub fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+        .map_err(|e| format!("invalid date '{}', expected YYYY-MM-DD ({})", s, e))
+}
+
+fn now_timestamp() -> i64 {
+    Local::now().timestamp()
+}
+
+/// 把 Unix 时间戳（秒）格式化为本地时间
+fn format_timestamp(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// 重复周期：每 N 天 / 周 / 月
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Recurrence {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+}
+
+// every:<N> 的 N 上限，避免推算日期时超出可表示的范围
+const MAX_RECURRENCE_COUNT: u32 = 1000;
+
+impl Recurrence {
+    /// 把日期推后一个周期；超出日期可表示的范围时返回 None
+    fn advance(&self, date: NaiveDate) -> Option<NaiveDate> {
+        match *self {
+            Recurrence::Days(n) => date.checked_add_days(Days::new(n as u64)),
+            Recurrence::Weeks(n) => date.checked_add_days(Days::new(n as u64 * 7)),
+            Recurrence::Months(n) => date.checked_add_months(Months::new(n)),
+        }
+    }
+}
+
+impl Display for Recurrence {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Recurrence::Days(1) => write!(f, "daily"),
+            Recurrence::Weeks(1) => write!(f, "weekly"),
+            Recurrence::Months(1) => write!(f, "monthly"),
+            Recurrence::Days(n) => write!(f, "every:{}d", n),
+            Recurrence::Weeks(n) => write!(f, "every:{}w", n),
+            Recurrence::Months(n) => write!(f, "every:{}m", n),
+        }
+    }
+}
+
+impl TryFrom<String> for Recurrence {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        parse_recurrence(&s)
+    }
+}
+
+impl From<Recurrence> for String {
+    fn from(recurrence: Recurrence) -> Self {
+        recurrence.to_string()
+    }
+}
+
+/// 解析重复周期：daily / weekly / monthly，或 every:3d、every:2w、every:1m
+pub fn parse_recurrence(s: &str) -> Result<Recurrence, String> {
+    let spec = s.trim().to_lowercase();
+    let recurrence = match spec.as_str() {
+        "daily" => Recurrence::Days(1),
+        "weekly" => Recurrence::Weeks(1),
+        "monthly" => Recurrence::Months(1),
+        _ => {
+            let invalid = || {
+                format!(
+                    "invalid recurrence '{}', expected daily, weekly, monthly or every:<N>d|w|m",
+                    s
+                )
+            };
+            let interval = spec.strip_prefix("every:").ok_or_else(invalid)?;
+            let unit = interval.chars().last().ok_or_else(invalid)?;
+            let count: u32 = interval[..interval.len() - unit.len_utf8()]
+                .parse()
+                .map_err(|_| invalid())?;
+            if count == 0 {
+                return Err(invalid());
+            }
+            if count > MAX_RECURRENCE_COUNT {
+                return Err(format!(
+                    "invalid recurrence '{}', N must be at most {}",
+                    s, MAX_RECURRENCE_COUNT
+                ));
+            }
+            match unit {
+                'd' => Recurrence::Days(count),
+                'w' => Recurrence::Weeks(count),
+                'm' => Recurrence::Months(count),
+                _ => return Err(invalid()),
+            }
+        }
+    };
+    Ok(recurrence)
+}
+
+/// 在原文件名后追加后缀得到同目录下的文件路径，如 `todo.todo` -> `todo.todo.tmp`
+pub fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// 回收站文件：同目录下的 `<name>.trash.todo`，删除的项保存在这里直到 empty-trash
+pub fn trash_path(path: &Path) -> PathBuf {
+    path.with_extension("trash.todo")
+}
+
+// 回收站本身删除的项直接丢弃，不再套一层回收站
+fn is_trash_path(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(".trash.todo")
+}
+
+/// 给待办文件加上建议锁（advisory lock），防止两个进程同时修改后互相覆盖。
+/// 保存时会用临时文件替换原文件，所以锁加在旁边固定不变的 `.lock` 文件上；
+/// 返回的文件句柄关闭时锁自动释放。
+pub fn lock_file(path: &Path) -> Result<fs::File, Box<dyn Error>> {
+    let lock = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(sibling_path(path, ".lock"))
+        .map_err(|e| format!("无法创建锁文件: {}", e))?;
+    match lock.try_lock() {
+        Ok(()) => Ok(lock),
+        Err(fs::TryLockError::WouldBlock) => Err("todo file is locked by another process.".into()),
+        Err(fs::TryLockError::Error(e)) => Err(format!("无法锁定文件: {}", e).into()),
+    }
+}
+
+/// 标签统一转为小写并去重，避免 `Work` 和 `work` 被当作两个分组
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// 第 index 份备份的路径：最新的为 `.bak`，更早的依次为 `.bak.1`、`.bak.2`……
+fn backup_path(path: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        sibling_path(path, ".bak")
+    } else {
+        sibling_path(path, &format!(".bak.{}", index))
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 追加时的轻量备份：不拷贝文件，只记下追加前后的长度（`<前> <后>`），撤销时截断回追加前的长度
+fn append_marker_path(path: &Path, index: usize) -> PathBuf {
+    let mut marker = backup_path(path, index).into_os_string();
+    marker.push(".len");
+    PathBuf::from(marker)
+}
+
+fn backup_exists(path: &Path, index: usize) -> bool {
+    backup_path(path, index).exists() || append_marker_path(path, index).exists()
+}
+
+/// 把第 from 份备份移到第 to 份，目标位置原有的备份（无论哪种）先删掉
+fn move_backup(path: &Path, from: usize, to: usize) -> io::Result<()> {
+    for slot in [backup_path, append_marker_path] {
+        remove_if_exists(&slot(path, to))?;
+    }
+    for slot in [backup_path, append_marker_path] {
+        if slot(path, from).exists() {
+            fs::rename(slot(path, from), slot(path, to))?;
+        }
+    }
+    Ok(())
+}
+
+/// 已有备份依次后移，为最新备份腾出位置，最多保留 keep 份；keep = 0 时返回 false
+fn shift_backups(path: &Path, keep: usize) -> io::Result<bool> {
+    if keep == 0 {
+        return Ok(false);
+    }
+    for index in (0..keep - 1).rev() {
+        if backup_exists(path, index) {
+            move_backup(path, index, index + 1)?;
+        }
+    }
+    Ok(true)
+}
+
+/// 把当前文件快照为最新备份，已有备份依次后移，最多保留 keep 份
+fn rotate_backups(path: &Path, keep: usize) -> io::Result<()> {
+    if shift_backups(path, keep)? {
+        // keep = 1 时没有移动，上一次留下的长度记录要删掉，否则会盖过这份快照
+        remove_if_exists(&append_marker_path(path, 0))?;
+        fs::copy(path, backup_path(path, 0))?;
+    }
+    Ok(())
+}
+
+/// 为追加记录最新备份：文件从 before 字节增长到 after 字节，其余与 rotate_backups 相同
+fn record_append(path: &Path, keep: usize, before: u64, after: u64) -> io::Result<()> {
+    if shift_backups(path, keep)? {
+        remove_if_exists(&backup_path(path, 0))?;
+        fs::write(append_marker_path(path, 0), format!("{} {}", before, after))?;
+    }
+    Ok(())
+}
+
+/// 用最新备份覆盖当前文件，其余备份依次前移；没有备份时返回 false。
+/// 最新备份是追加记录时把文件截断回追加前的长度，文件长度与记录不符（追加后又被改过）时报错
+pub fn restore_backup(path: &Path) -> io::Result<bool> {
+    let marker = append_marker_path(path, 0);
+    if marker.exists() {
+        let recorded = fs::read_to_string(&marker)?;
+        let lengths = recorded
+            .split_once(' ')
+            .and_then(|(before, after)| Some((before.parse().ok()?, after.parse().ok()?)));
+        let Some((before, after)): Option<(u64, u64)> = lengths else {
+            return Err(io::Error::other(format!(
+                "{} is not a valid backup record",
+                marker.display()
+            )));
+        };
+        let file = OpenOptions::new().write(true).open(path)?;
+        if file.metadata()?.len() != after {
+            return Err(io::Error::other(
+                "the file was changed outside of todo after the last add",
+            ));
+        }
+        file.set_len(before)?;
+        file.sync_all()?;
+        fs::remove_file(&marker)?;
+    } else if backup_path(path, 0).exists() {
+        fs::rename(backup_path(path, 0), path)?;
+    } else {
+        return Ok(false);
+    }
+    let mut index = 1;
+    while backup_exists(path, index) {
+        move_backup(path, index, index - 1)?;
+        index += 1;
+    }
+    Ok(true)
+}
+
+/// 永久删除文件及其所有备份，用于清空回收站；文件不存在时什么也不做
+pub fn remove_with_backups(path: &Path) -> io::Result<()> {
+    remove_if_exists(path)?;
+    let mut index = 0;
+    while backup_exists(path, index) {
+        remove_if_exists(&backup_path(path, index))?;
+        remove_if_exists(&append_marker_path(path, index))?;
+        index += 1;
+    }
+    Ok(())
+}
+
+/// 对比两份列表（按 id 对应），返回每项改动的描述：- 删除、+ 新增、~ 修改
+fn describe_changes(before: &[TodoItem], after: &[TodoItem]) -> Vec<String> {
+    let mut changes = Vec::new();
+    for old in before {
+        match after.iter().find(|item| item.id == old.id) {
+            None => changes.push(format!("- {}", old.summary())),
+            Some(new) if new != old => changes.push(format!("~ {}", new.summary())),
+            Some(_) => {}
+        }
+    }
+    for new in after {
+        if !before.iter().any(|item| item.id == new.id) {
+            changes.push(format!("+ {}", new.summary()));
+        }
+    }
+    changes
+}
+
+fn print_dry_run(path: &Path, changes: &[String]) {
+    // 只读命令没有改动，不必提示
+    if changes.is_empty() {
+        return;
+    }
+    println!(
+        "Dry run: {} was not changed. Pending changes:",
+        path.display()
+    );
+    for change in changes {
+        println!("  {}", change);
+    }
+}
+
+/// 解析批量添加的文本：忽略空行和 # 开头的注释，`::` 之后的部分作为内容
+pub fn parse_bulk_lines(text: &str, priority: i16) -> Vec<TodoItem> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (name, content) = line.split_once("::").unwrap_or((line, ""));
+            TodoItem::new(
+                name.trim().to_string(),
+                content.trim().to_string(),
+                priority,
+            )
+        })
+        .collect()
+}
+
+/// 把待办项渲染为 Markdown 任务列表，按传入顺序逐行输出
+pub fn to_markdown(items: &[TodoItem]) -> String {
+    let mut markdown = String::new();
+    for item in items {
+        let mark = if item.completed { "x" } else { " " };
+        // 内容中的换行会打断列表项，渲染时合并为一行
+        let content = item.content.lines().collect::<Vec<_>>().join(" ");
+        markdown.push_str(&format!(
+            "- [{}] {} (priority {}): {}\n",
+            mark, item.name, item.priority, content
+        ));
+    }
+    markdown
+}
+
+/// CSV 中的一行，只包含表格用户关心的三列
+#[derive(Debug, Deserialize, Serialize)]
+struct CsvRow {
+    name: String,
+    content: String,
+    priority: i16,
+}
+
+pub fn to_csv(items: &[TodoItem]) -> Result<String, Box<dyn Error>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    for item in items {
+        writer.serialize(CsvRow {
+            name: item.name.clone(),
+            content: item.content.clone(),
+            priority: item.priority,
+        })?;
+    }
+    Ok(String::from_utf8(writer.into_inner()?)?)
+}
+
+pub fn from_csv<R: Read>(reader: R) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let mut items = Vec::new();
+    for row in reader.deserialize() {
+        let row: CsvRow = row?;
+        items.push(TodoItem::new(row.name, row.content, row.priority));
+    }
+    Ok(items)
+}
+
+/// GitHub issues API 返回的 issue 中用到的字段
+#[derive(Deserialize)]
+struct GithubIssue {
+    title: String,
+    body: Option<String>,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    labels: Vec<GithubLabel>,
+    // issues 接口同样会返回 pull request，它们带有这个字段
+    #[serde(default)]
+    pull_request: Option<serde::de::IgnoredAny>,
+}
+
+// 标签一般是对象，部分接口和手写的导出文件里是字符串
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum GithubLabel {
+    Object { name: String },
+    Name(String),
+}
+
+/// 解析 GitHub issues API 返回的 JSON 数组：title 作为名称，body 作为内容，标签作为 tags；
+/// 跳过其中的 pull request；默认也跳过已关闭的 issue，导入时标为已完成
+pub fn from_github_issues<R: Read>(
+    reader: R,
+    priority: i16,
+    include_closed: bool,
+) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+    let issues: Vec<GithubIssue> = serde_json::from_reader(reader)?;
+    let items = issues
+        .into_iter()
+        .filter(|issue| issue.pull_request.is_none())
+        .filter(|issue| include_closed || issue.state != "closed")
+        .map(|issue| {
+            let content = issue.body.unwrap_or_default().replace("\r\n", "\n");
+            let mut item = TodoItem::new(issue.title.trim().to_string(), content, priority);
+            item.completed = issue.state == "closed";
+            item.tags = normalize_tags(
+                issue
+                    .labels
+                    .into_iter()
+                    .map(|label| match label {
+                        GithubLabel::Object { name } | GithubLabel::Name(name) => name,
+                    })
+                    .collect(),
+            );
+            item
+        })
+        .collect();
+    Ok(items)
+}
+
+// Taskwarrior 的时间一律为 UTC，如 20240105T230000Z
+const TASKWARRIOR_DATE: &str = "%Y%m%dT%H%M%SZ";
+
+/// `task export` 输出的一项中用到的字段
+#[derive(Deserialize, Serialize)]
+struct TaskwarriorTask {
+    description: String,
+    status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    priority: Option<String>, // H / M / L
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    // 内容保存为注释，导入时各条注释按行合并为内容
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<TaskwarriorAnnotation>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct TaskwarriorAnnotation {
+    entry: String,
+    description: String,
+}
+
+fn taskwarrior_time(timestamp: i64) -> Option<String> {
+    DateTime::from_timestamp(timestamp, 0).map(|time| time.format(TASKWARRIOR_DATE).to_string())
+}
+
+fn parse_taskwarrior_time(s: &str) -> Result<DateTime<chrono::Utc>, String> {
+    chrono::NaiveDateTime::parse_from_str(s, TASKWARRIOR_DATE)
+        .map(|time| time.and_utc())
+        .map_err(|_| {
+            format!(
+                "invalid Taskwarrior date '{}', expected YYYYMMDDTHHMMSSZ",
+                s
+            )
+        })
+}
+
+/// 输出 `task import` 可读取的 JSON 数组，每项一行。截止日期按本地时区的零点换算为 UTC，
+/// 优先级按等级对应 H / M / L，0 和 backlog 不设优先级
+pub fn to_taskwarrior(items: &[TodoItem]) -> Result<String, Box<dyn Error>> {
+    let now = now_timestamp();
+    let mut lines = Vec::with_capacity(items.len());
+    for item in items {
+        let priority = match item.level() {
+            Priority::High | Priority::Critical => Some("H"),
+            Priority::Normal => Some("M"),
+            Priority::Low if item.priority > 0 => Some("L"),
+            Priority::Low | Priority::Backlog => None,
+        };
+        let due = item.due.and_then(|due| {
+            due.and_hms_opt(0, 0, 0)?
+                .and_local_timezone(Local)
+                .earliest()
+                .map(|time| time.to_utc().format(TASKWARRIOR_DATE).to_string())
+        });
+        let entry = if item.created_at != 0 {
+            item.created_at
+        } else {
+            now
+        };
+        let modified = item.updated_at.max(entry);
+        let annotations = if item.content.trim().is_empty() {
+            Vec::new()
+        } else {
+            vec![TaskwarriorAnnotation {
+                entry: taskwarrior_time(entry).unwrap_or_default(),
+                description: item.content.clone(),
+            }]
+        };
+        let task = TaskwarriorTask {
+            description: item.name.clone(),
+            status: if item.completed {
+                "completed"
+            } else {
+                "pending"
+            }
+            .to_string(),
+            priority: priority.map(str::to_string),
+            due,
+            entry: taskwarrior_time(entry),
+            modified: taskwarrior_time(modified),
+            end: if item.completed {
+                taskwarrior_time(modified)
+            } else {
+                None
+            },
+            tags: item.tags.clone(),
+            annotations,
+        };
+        lines.push(serde_json::to_string(&task)?);
+    }
+    Ok(format!("[\n{}\n]\n", lines.join(",\n")))
+}
+
+/// 读取 `task export` 的 JSON 数组：description 作为名称，注释作为内容，H / M / L 对应
+/// high / normal / low，没有优先级的项使用 priority；已删除和周期任务的模板不导入
+pub fn from_taskwarrior<R: Read>(
+    reader: R,
+    priority: i16,
+) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+    let tasks: Vec<TaskwarriorTask> = serde_json::from_reader(reader)?;
+    let mut items = Vec::new();
+    for task in tasks {
+        if task.status == "deleted" || task.status == "recurring" {
+            continue;
+        }
+        let priority = match task.priority.as_deref() {
+            Some("H") => Priority::High.into(),
+            Some("M") => Priority::Normal.into(),
+            Some("L") => Priority::Low.into(),
+            _ => priority,
+        };
+        let content = task
+            .annotations
+            .iter()
+            .map(|annotation| annotation.description.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut item = TodoItem::new(task.description.trim().to_string(), content, priority);
+        item.completed = task.status == "completed";
+        item.tags = normalize_tags(task.tags);
+        if let Some(due) = &task.due {
+            item.due = Some(
+                parse_taskwarrior_time(due)?
+                    .with_timezone(&Local)
+                    .date_naive(),
+            );
+        }
+        if let Some(entry) = &task.entry {
+            item.created_at = parse_taskwarrior_time(entry)?.timestamp();
+        }
+        if let Some(modified) = &task.modified {
+            item.updated_at = parse_taskwarrior_time(modified)?.timestamp();
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    total: usize,
+    completed: usize,
+    backlog: usize, // 优先级为负数的项
+    mean_priority: f64,
+    by_priority: BTreeMap<i16, usize>,
+    by_tag: BTreeMap<String, usize>,
+    content_chars: usize, // 按字符（而非字节）计数
+    content_words: usize,
+    mean_content_length: f64,
+    longest_item: Option<String>, // 内容最长的项，内容都为空时为 None
+}
+
+pub fn compute_stats(items: &[TodoItem]) -> Stats {
+    let mut by_priority = BTreeMap::new();
+    let mut by_tag = BTreeMap::new();
+    for item in items {
+        *by_priority.entry(item.priority).or_insert(0) += 1;
+        for tag in &item.tags {
+            *by_tag.entry(tag.clone()).or_insert(0) += 1;
+        }
+    }
+    let mean_priority = if items.is_empty() {
+        0.0
+    } else {
+        items.iter().map(|item| item.priority as f64).sum::<f64>() / items.len() as f64
+    };
+    let content_chars = items.iter().map(|item| item.content.chars().count()).sum();
+    let mean_content_length = if items.is_empty() {
+        0.0
+    } else {
+        content_chars as f64 / items.len() as f64
+    };
+    let longest_item = items
+        .iter()
+        .filter(|item| !item.content.is_empty())
+        .max_by_key(|item| item.content.chars().count())
+        .map(|item| item.name.clone());
+    Stats {
+        total: items.len(),
+        completed: items.iter().filter(|item| item.completed).count(),
+        backlog: items
+            .iter()
+            .filter(|item| is_backlog(item.priority))
+            .count(),
+        mean_priority,
+        by_priority,
+        by_tag,
+        content_chars,
+        content_words: items
+            .iter()
+            .map(|item| item.content.split_whitespace().count())
+            .sum(),
+        mean_content_length,
+        longest_item,
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Total: {} (completed: {})", self.total, self.completed)?;
+        if self.backlog > 0 {
+            writeln!(f, "Backlog: {}", self.backlog)?;
+        }
+        writeln!(f, "Mean priority: {:.2}", self.mean_priority)?;
+        writeln!(f, "By priority:")?;
+        for (priority, count) in self.by_priority.iter().rev() {
+            writeln!(f, "  {}: {}", priority, count)?;
+        }
+        if !self.by_tag.is_empty() {
+            writeln!(f, "By tag:")?;
+            for (tag, count) in &self.by_tag {
+                writeln!(f, "  {}: {}", tag, count)?;
+            }
+        }
+        writeln!(
+            f,
+            "Content: {} characters, {} words (mean length {:.1})",
+            self.content_chars, self.content_words, self.mean_content_length
+        )?;
+        if let Some(name) = &self.longest_item {
+            writeln!(f, "Longest content: {}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    Priority,
+    Name,
+    Created,
+    Due,
+    Manual,
+}
+
+/// 按指定字段排序：优先级从高到低，名称按字母顺序（忽略大小写），
+/// 截止日期从早到晚、没有截止日期的排在最后，手动顺序按 reorder 设置的位置
+/// （没排过的保持原有顺序排在最后）；reverse 反转结果
+pub fn sort_items(items: &mut [TodoItem], field: SortField, reverse: bool) {
+    match field {
+        SortField::Priority => items.sort_by_key(|item| std::cmp::Reverse(item.priority_key())),
+        SortField::Name => items.sort_by_key(|item| item.name.to_lowercase()),
+        SortField::Created => items.sort_by_key(|item| item.created_at),
+        SortField::Due => items.sort_by_key(|item| (item.due.is_none(), item.due)),
+        SortField::Manual => items.sort_by_key(|item| (item.order == 0, item.order)),
+    }
+    if reverse {
+        items.reverse();
+    }
+}
+
+/// dedup 判定重复的依据，名称都忽略大小写（与 add 的重名检查一致）
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum DedupKey {
+    Name,
+    NameContent,
+}
+
+/// 存储格式版本，写入每个条目的 schema_version 字段；以后格式变化时，打开文件时可据此转换旧数据。
+/// 没有该字段的旧文件读作 0
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq, Property)]
+pub struct TodoItem {
+    #[serde(default)]
+    pub id: u64, // 0 表示尚未分配，打开文件时会补齐
+    pub name: String,
+    pub content: String,
+    pub priority: i16,
+    #[serde(default)]
+    pub completed: bool, // 旧文件中没有该字段时默认为未完成
+    #[serde(default)]
+    pub due: Option<NaiveDate>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub url: Option<String>, // 相关网页，`todo open` 用默认浏览器打开
+    #[serde(default)]
+    pub created_at: i64, // Unix 时间戳（秒），旧文件中为 0 表示未知
+    #[serde(default)]
+    pub updated_at: i64,
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    #[serde(default)]
+    pub parent: Option<u64>, // 父任务的 id，None 表示顶层任务
+    #[serde(default)]
+    pub order: u32, // 手动排序的位置，从 1 开始；0 表示尚未排过，排在最后
+    #[serde(default)]
+    pub schema_version: u32, // 写入该条目时的存储格式版本（SCHEMA_VERSION）
+    // 本版本不认识的字段（如更新版本写入的），原样保存，避免共用的文件被旧版本抹掉数据
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TodoItem {
+    /// priority 可以是数值，也可以是 Priority 等级
+    pub fn new(name: String, content: String, priority: impl Into<i16>) -> Self {
+        let now = now_timestamp();
+        TodoItem {
+            id: 0, // 由 add_item 分配
+            name,
+            content,
+            priority: priority.into(),
+            completed: false,
+            due: None,
+            tags: Vec::new(),
+            url: None,
+            created_at: now,
+            updated_at: now,
+            recurrence: None,
+            parent: None,
+            order: 0,
+            schema_version: SCHEMA_VERSION,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    pub fn level(&self) -> Priority {
+        Priority::from(self.priority)
+    }
+
+    /// 按优先级排序的键：先比较等级，同一等级内再比较数值
+    pub fn priority_key(&self) -> (Priority, i16) {
+        (self.level(), self.priority)
+    }
+
+    pub fn is_overdue(&self, today: NaiveDate) -> bool {
+        self.due.is_some_and(|due| due < today)
+    }
+
+    pub fn has_all_tags(&self, tags: &[String]) -> bool {
+        tags.iter().all(|tag| self.tags.contains(tag))
+    }
+
+    /// 选择列表中每一行的简要描述
+    pub fn summary(&self) -> String {
+        format!(
+            "{} (ID: {}, Priority: {})",
+            self.name, self.id, self.priority
+        )
+    }
+}
+
+// 颜色仅在 stdout 为终端、未设置 NO_COLOR 且未指定 --no-color 时生效
+/// 优先级的显示样式：高优先级醒目，低优先级淡化
+pub fn priority_style(priority: i16) -> Style {
+    match Priority::from(priority) {
+        Priority::High | Priority::Critical => Style::new().red().bold(),
+        Priority::Backlog | Priority::Low => Style::new().dimmed(),
+        Priority::Normal => Style::new(),
+    }
+}
+
+/// 用相对说法描述截止日期，如 "due in 2 days"、"due today"、"overdue by 3 days"
+pub fn humanize_due(due: NaiveDate, now: NaiveDate) -> String {
+    let days = (due - now).num_days();
+    let plural = |n: i64| if n == 1 { "day" } else { "days" };
+    match days {
+        0 => "due today".to_string(),
+        1 => "due tomorrow".to_string(),
+        n if n > 0 => format!("due in {} {}", n, plural(n)),
+        n => format!("overdue by {} {}", -n, plural(-n)),
+    }
+}
+
+/// 用于显示的内容行：去掉首尾空行和行尾空白，连续的空行合并为一行
+fn content_lines(content: &str) -> Vec<&str> {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in content.lines().map(str::trim_end) {
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    if lines.last().is_some_and(|last| last.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+impl Display for TodoItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mark = if self.completed { "[x]" } else { "[ ]" };
+        writeln!(
+            f,
+            "{} Item: {} (ID: {}) ",
+            mark,
+            self.name
+                .if_supports_color(Stream::Stdout, |name| name.bold()),
+            self.id
+        )?;
+        // 内容为空时不显示这一行；多行内容的后续行与第一行对齐
+        let lines = content_lines(&self.content);
+        if let Some((first, rest)) = lines.split_first() {
+            writeln!(f, "Content: {} ", first)?;
+            for line in rest {
+                if line.is_empty() {
+                    writeln!(f)?;
+                } else {
+                    writeln!(f, "{}{} ", " ".repeat("Content: ".len()), line)?;
+                }
+            }
+        }
+        if let Some(due) = self.due {
+            if self.completed {
+                writeln!(f, "Due: {} ", due)?;
+            } else {
+                let today = Local::now().date_naive();
+                let style = if due < today {
+                    Style::new().red().bold()
+                } else if due == today {
+                    Style::new().yellow()
+                } else {
+                    Style::new()
+                };
+                writeln!(
+                    f,
+                    "Due: {} ({}) ",
+                    humanize_due(due, today)
+                        .if_supports_color(Stream::Stdout, |text| text.style(style)),
+                    due
+                )?;
+            }
+        }
+        if let Some(url) = &self.url {
+            writeln!(f, "URL: {} ", url)?;
+        }
+        if !self.tags.is_empty() {
+            writeln!(f, "Tags: {} ", self.tags.join(", "))?;
+        }
+        if let Some(recurrence) = self.recurrence {
+            writeln!(f, "Repeat: {} ", recurrence)?;
+        }
+        if let Some(parent) = self.parent {
+            writeln!(f, "Parent: {} ", parent)?;
+        }
+        if self.order != 0 {
+            writeln!(f, "Order: {} ", self.order)?;
+        }
+        if self.created_at != 0 {
+            writeln!(
+                f,
+                "Created: {}, Updated: {} ",
+                format_timestamp(self.created_at),
+                format_timestamp(self.updated_at)
+            )?;
+        }
+        write!(
+            f,
+            "{}",
+            format!("(Priority: {})", self.priority)
+                .if_supports_color(Stream::Stdout, |priority| {
+                    priority.style(priority_style(self.priority))
+                })
+        )
+    }
+}
+
+/// 编辑时要修改的字段，为 None 的字段保持不变
+#[derive(Debug, Clone, Default)]
+pub struct ItemChanges {
+    pub name: Option<String>,
+    pub content: Option<String>,
+    pub priority: Option<i16>,
+    pub url: Option<String>,
+}
+
+impl ItemChanges {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.content.is_none()
+            && self.priority.is_none()
+            && self.url.is_none()
+    }
+
+    fn apply(self, item: &mut TodoItem) {
+        if let Some(name) = self.name {
+            item.name = name;
+        }
+        if let Some(content) = self.content {
+            item.content = content;
+        }
+        if let Some(priority) = self.priority {
+            item.priority = priority;
+        }
+        if let Some(url) = self.url {
+            item.url = Some(url);
+        }
+    }
+}
+
+/// 待办文件的存储布局：整个 JSON 数组，或每行一个 JSON 对象（NDJSON）
+enum StorageLayout {
+    Empty,
+    Array,
+    Lines,
+}
+
+/// 跳过开头的空白，根据第一个有效字符判断存储布局，不会一次性读入整个文件
+fn detect_layout<R: BufRead>(reader: &mut R) -> io::Result<StorageLayout> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            return Ok(StorageLayout::Empty);
+        }
+        match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+            Some(i) => {
+                let layout = if buf[i] == b'[' {
+                    StorageLayout::Array
+                } else {
+                    StorageLayout::Lines
+                };
+                reader.consume(i);
+                return Ok(layout);
+            }
+            None => {
+                let len = buf.len();
+                reader.consume(len);
+            }
+        }
+    }
+}
+
+/// 待办文件存在但内容无法解析（空文件或只有空白不算损坏，按空列表处理）
+#[derive(Debug)]
+pub struct CorruptFile {
+    pub path: PathBuf,
+    reason: String,
+}
+
+impl CorruptFile {
+    pub fn new(path: &Path, reason: impl Display) -> Self {
+        CorruptFile {
+            path: path.to_path_buf(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for CorruptFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} is not a valid todo file: {}",
+            self.path.display(),
+            self.reason
+        )?;
+        write!(
+            f,
+            "Recover what can be parsed with `todo repair --path {}`, or restore the latest backup with `todo undo --path {}`.",
+            self.path.display(),
+            self.path.display()
+        )
+    }
+}
+
+impl Error for CorruptFile {}
+
+/// 直接从 reader 反序列化，避免先把文件读成一个大字符串
+fn read_items<R: BufRead>(mut reader: R) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+    Ok(match detect_layout(&mut reader)? {
+        StorageLayout::Empty => Vec::new(),
+        StorageLayout::Array => serde_json::from_reader(reader)?,
+        StorageLayout::Lines => serde_json::Deserializer::from_reader(reader)
+            .into_iter::<TodoItem>()
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+/// 待办文件的序列化方式，由文件扩展名决定（见 storage_for）。
+/// 读取时直接从 reader 解析，大文件不必先整体读成字符串。
+pub trait Storage {
+    fn serialize(&self, items: &[TodoItem]) -> Result<String, Box<dyn Error>>;
+    fn deserialize(&self, reader: &mut dyn BufRead) -> Result<Vec<TodoItem>, Box<dyn Error>>;
+}
+
+/// `.todo`：每行一个 JSON 对象，读取时兼容旧的 JSON 数组格式
+pub struct JsonStorage;
+
+impl Storage for JsonStorage {
+    fn serialize(&self, items: &[TodoItem]) -> Result<String, Box<dyn Error>> {
+        // 字段按结构体声明顺序输出，每次运行都一致；读取时两种格式都能解析
+        if PRETTY_JSON.load(Ordering::Relaxed) {
+            return Ok(serde_json::to_string_pretty(items)?);
+        }
+        // 默认每行一个条目（NDJSON）：新增时可以直接追加一行，用 git 按行对比也很清晰
+        let mut serialized = String::new();
+        for item in items {
+            serialized.push_str(&serde_json::to_string(item)?);
+            serialized.push('\n');
+        }
+        Ok(serialized)
+    }
+
+    fn deserialize(&self, reader: &mut dyn BufRead) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+        read_items(reader)
+    }
+}
+
+/// `.todo.toml`：每项一个 `[[items]]` 表，方便手工编辑
+pub struct TomlStorage;
+
+#[derive(Deserialize, Serialize, Default)]
+struct TomlDocument {
+    #[serde(default)]
+    items: Vec<TodoItem>,
+}
+
+impl Storage for TomlStorage {
+    fn serialize(&self, items: &[TodoItem]) -> Result<String, Box<dyn Error>> {
+        let document = TomlDocument {
+            items: items.to_vec(),
+        };
+        Ok(toml::to_string(&document)?)
+    }
+
+    fn deserialize(&self, reader: &mut dyn BufRead) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        Ok(toml::from_str::<TomlDocument>(&text)?.items)
+    }
+}
+
+pub fn is_toml_path(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.ends_with(".todo.toml"))
+}
+
+fn storage_for(path: &Path) -> &'static dyn Storage {
+    if is_toml_path(path) {
+        &TomlStorage
+    } else {
+        &JsonStorage
+    }
+}
+
+/// 条目的 JSON Schema，供读写待办文件的外部工具检查兼容性；
+/// `.todo` 每行一个这样的对象，`.todo.toml` 的每个 `[[items]]` 表字段相同
+pub fn item_schema() -> serde_json::Value {
+    let nullable = |kind: &str| serde_json::json!({ "type": [kind, "null"] });
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "TodoItem",
+        "description": format!("todo_cli storage schema version {}", SCHEMA_VERSION),
+        "type": "object",
+        "required": ["name", "content", "priority"],
+        "properties": {
+            "id": { "type": "integer", "minimum": 0, "description": "0 means not assigned yet" },
+            "name": { "type": "string", "minLength": 1 },
+            "content": { "type": "string" },
+            "priority": {
+                "type": "integer",
+                "minimum": PRIORITY_RANGE.start(),
+                "maximum": PRIORITY_RANGE.end(),
+                "description": "negative values are backlog"
+            },
+            "completed": { "type": "boolean", "default": false },
+            "due": { "type": ["string", "null"], "format": "date" },
+            "tags": { "type": "array", "items": { "type": "string" }, "default": [] },
+            "url": { "type": ["string", "null"], "format": "uri" },
+            "created_at": { "type": "integer", "description": "Unix timestamp in seconds, 0 if unknown" },
+            "updated_at": { "type": "integer", "description": "Unix timestamp in seconds, 0 if unknown" },
+            "recurrence": {
+                "type": ["string", "null"],
+                "pattern": "^(daily|weekly|monthly|every:[0-9]+[dwm])$"
+            },
+            "parent": nullable("integer"),
+            "order": { "type": "integer", "minimum": 0, "description": "manual order, 0 means unranked" },
+            "schema_version": { "type": "integer", "const": SCHEMA_VERSION }
+        },
+        "additionalProperties": true
+    })
+}
+
+/// 尽量从损坏的文件中恢复条目，返回恢复出的条目和丢弃的片段数：
+/// 逐行格式跳过无法解析的行；数组格式从头依次解析，遇到截断或损坏的对象时丢弃其后的内容。
+pub fn repair_items(text: &str) -> (Vec<TodoItem>, usize) {
+    let mut items = Vec::new();
+    let mut dropped = 0;
+    let trimmed = text.trim_start();
+    if let Some(mut rest) = trimmed.strip_prefix('[') {
+        loop {
+            rest = rest.trim_start().trim_start_matches(',').trim_start();
+            if rest.is_empty() || rest.starts_with(']') {
+                break;
+            }
+            let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<TodoItem>();
+            match stream.next() {
+                Some(Ok(item)) => {
+                    items.push(item);
+                    rest = &rest[stream.byte_offset()..];
+                }
+                _ => {
+                    dropped += 1;
+                    break;
+                }
+            }
+        }
+    } else {
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(item) => items.push(item),
+                Err(_) => dropped += 1,
+            }
+        }
+    }
+    (items, dropped)
+}
+
+/// 追加新项前扫描已有条目时只需要 id 和名称，其余字段跳过不解析
+#[derive(Deserialize)]
+struct ItemHead {
+    #[serde(default)]
+    id: u64,
+    name: String,
+}
+
+/// 完整解析逐行格式文件中的第 position 项，只在追加遇到重名、需要展示冲突项时使用
+fn read_item_at(path: &Path, position: usize) -> Result<TodoItem, Box<dyn Error>> {
+    let file = fs::File::open(path)?;
+    serde_json::Deserializer::from_reader(BufReader::new(file))
+        .into_iter::<TodoItem>()
+        .nth(position)
+        .ok_or("文件在读取期间被修改")?
+        .map_err(|e| CorruptFile::new(path, e).into())
+}
+
+/// 统计未完成项时只需要完成状态，其余字段跳过不解析
+#[derive(Deserialize)]
+struct ItemStatus {
+    #[serde(default)]
+    completed: bool,
+}
+
+/// 只统计未完成的条目数量，解析时跳过完成状态以外的内容，不构造 TodoItem
+pub fn count_pending<R: BufRead>(mut reader: R) -> Result<usize, Box<dyn Error>> {
+    Ok(match detect_layout(&mut reader)? {
+        StorageLayout::Empty => 0,
+        StorageLayout::Array => serde_json::from_reader::<_, Vec<ItemStatus>>(reader)?
+            .iter()
+            .filter(|item| !item.completed)
+            .count(),
+        StorageLayout::Lines => {
+            let mut count = 0;
+            for item in serde_json::Deserializer::from_reader(reader).into_iter::<ItemStatus>() {
+                if !item?.completed {
+                    count += 1;
+                }
+            }
+            count
+        }
+    })
+}
+
+// 待办文件小于该大小时不使用索引：整体解析本来就很快，不值得多维护一个文件
+const INDEX_MIN_BYTES: u64 = 256 * 1024;
+
+/// 待办文件旁的 `.idx` 索引：每行条目的小写名称及其在文件中的字节范围，find 按名称查找时只解析命中的行。
+/// 同时记下建立索引时待办文件的大小和修改时间，任一不符即视为过期并重建
+#[derive(Serialize, Deserialize)]
+struct NameIndex {
+    source_len: u64,
+    source_mtime_ns: u128,
+    entries: Vec<(String, u64, u64)>, // (小写名称, 行首偏移, 行长度)
+}
+
+fn mtime_ns(metadata: &fs::Metadata) -> Option<u128> {
+    let modified = metadata.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_nanos())
+}
+
+/// 在逐行格式的大文件中按名称（忽略大小写）查找，结果与 find_items_by_name 相同。
+/// 不适用索引（TOML、数组格式、小文件）或读取出错时返回 None，由调用方整体读取文件。
+/// 与 snapshot 一样不加锁：保存是原子替换，读到的总是某个完整版本
+pub fn find_by_name_indexed(path: &Path, keyword: &str) -> Option<Vec<TodoItem>> {
+    if is_toml_path(path) {
+        return None;
+    }
+    // 读取期间文件可能被替换，始终使用同一个文件句柄，元数据和偏移量才对得上
+    let mut file = fs::File::open(path).ok()?;
+    let metadata = file.metadata().ok()?;
+    if metadata.len() < INDEX_MIN_BYTES {
+        return None;
+    }
+    let (len, mtime) = (metadata.len(), mtime_ns(&metadata)?);
+    let index_path = sibling_path(path, ".idx");
+    let cached = fs::read(&index_path)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice::<NameIndex>(&bytes).ok());
+    let index = match cached {
+        Some(index) if index.source_len == len && index.source_mtime_ns == mtime => index,
+        _ => {
+            let index = build_name_index(&mut file, len, mtime)?;
+            // 索引写不进去（如目录只读）不影响本次查找
+            let _ = write_name_index(&index_path, &index);
+            index
+        }
+    };
+
+    let keyword_lower = keyword.to_lowercase();
+    let mut found = Vec::new();
+    for (name, offset, line_len) in &index.entries {
+        if !name.contains(&keyword_lower) {
+            continue;
+        }
+        let mut line = vec![0; usize::try_from(*line_len).ok()?];
+        file.seek(SeekFrom::Start(*offset)).ok()?;
+        file.read_exact(&mut line).ok()?;
+        let item: TodoItem = serde_json::from_slice(&line).ok()?;
+        // 旧文件中没有 id 的项要等打开时才分配，这里给不出一致的 id
+        if item.id == 0 {
+            return None;
+        }
+        found.push(item);
+    }
+    Some(found)
+}
+
+/// 逐行读取名称建立索引；遇到无法解析的行（包括数组格式开头的 `[`）返回 None
+fn build_name_index(file: &mut fs::File, len: u64, mtime: u128) -> Option<NameIndex> {
+    #[derive(Deserialize)]
+    struct NameOnly {
+        name: String,
+    }
+    file.seek(SeekFrom::Start(0)).ok()?;
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    let (mut offset, mut line) = (0, Vec::new());
+    loop {
+        line.clear();
+        let read = reader.read_until(b'\n', &mut line).ok()? as u64;
+        if read == 0 {
+            break;
+        }
+        let trimmed = line.trim_ascii();
+        if !trimmed.is_empty() {
+            let NameOnly { name } = serde_json::from_slice(trimmed).ok()?;
+            let leading = (line.len() - line.trim_ascii_start().len()) as u64;
+            entries.push((name.to_lowercase(), offset + leading, trimmed.len() as u64));
+        }
+        offset += read;
+    }
+    Some(NameIndex {
+        source_len: len,
+        source_mtime_ns: mtime,
+        entries,
+    })
+}
+
+fn write_name_index(index_path: &Path, index: &NameIndex) -> Result<(), Box<dyn Error>> {
+    let tmp_path = sibling_path(index_path, ".tmp");
+    fs::write(&tmp_path, serde_json::to_vec(index)?)?;
+    fs::rename(&tmp_path, index_path)?;
+    Ok(())
+}
+
+/// add_item 拒绝新条目的原因
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddError {
+    Duplicate(String), // 已存在的同名项的名称
+    InvalidName(String),
+    PriorityOutOfRange(i16),
+}
+
+impl Display for AddError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AddError::Duplicate(name) => write!(f, "an item named {} already exists", name),
+            AddError::InvalidName(reason) => write!(f, "invalid name: {}", reason),
+            AddError::PriorityOutOfRange(priority) => write!(
+                f,
+                "priority {} is out of range, expected {} to {}",
+                priority,
+                PRIORITY_RANGE.start(),
+                PRIORITY_RANGE.end()
+            ),
+        }
+    }
+}
+
+impl Error for AddError {}
+
+/// 检查名称和优先级，名称改为去掉首尾空白后的结果（导入和批量添加的名称也和命令行输入的一致）；
+/// 重名检查需要列表内容，由调用方负责
+fn check_new_item(item: &mut TodoItem) -> Result<(), AddError> {
+    item.name = validate_name(&item.name).map_err(AddError::InvalidName)?;
+    if !PRIORITY_RANGE.contains(&item.priority) {
+        return Err(AddError::PriorityOutOfRange(item.priority));
+    }
+    Ok(())
+}
+
+/// 追加新条目的结果，重名时带回已存在的那一项
+pub enum AppendOutcome {
+    Added(TodoItem),
+    Duplicate(TodoItem),
+    MissingParent(u64),
+    Rejected(AddError), // 名称或优先级不合法
+}
+
+/// 两个列表按名称（忽略大小写）对应后的差异
+#[derive(Debug, Default, Serialize)]
+pub struct ListDiff {
+    pub only_in_a: Vec<TodoItem>,
+    pub only_in_b: Vec<TodoItem>,
+    pub changed: Vec<ChangedItem>,
+}
+
+impl ListDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// 两边都有但内容不同的项，fields 为不同的字段名
+#[derive(Debug, Serialize)]
+pub struct ChangedItem {
+    pub a: TodoItem,
+    pub b: TodoItem,
+    pub fields: Vec<&'static str>,
+}
+
+/// 比较用户可见的字段；id、时间戳和手动顺序在不同文件间本来就可能不同，不算差异
+pub fn changed_fields(a: &TodoItem, b: &TodoItem) -> Vec<&'static str> {
+    let mut fields = Vec::new();
+    if a.content != b.content {
+        fields.push("content");
+    }
+    if a.priority != b.priority {
+        fields.push("priority");
+    }
+    if a.completed != b.completed {
+        fields.push("completed");
+    }
+    if a.due != b.due {
+        fields.push("due");
+    }
+    if a.tags != b.tags {
+        fields.push("tags");
+    }
+    if a.url != b.url {
+        fields.push("url");
+    }
+    if a.recurrence != b.recurrence {
+        fields.push("recurrence");
+    }
+    fields
+}
+
+/// 为 a 中的每一项在 b 中找对应项，返回 b 中的下标（None 表示没有对应项），b 中每项至多对应一次。
+/// 先对应 id 和名称（忽略大小写）都相同的项；不同文件中的 id 各自分配，剩下的再按名称依次对应。
+/// 同名的多项（如重复任务完成后的旧项和新项）各自对应，不会合并成一项
+fn match_items(a: &[TodoItem], b: &[TodoItem]) -> Vec<Option<usize>> {
+    let mut by_id: HashMap<(u64, String), VecDeque<usize>> = HashMap::new();
+    let mut by_name: HashMap<String, VecDeque<usize>> = HashMap::new();
+    for (j, item) in b.iter().enumerate() {
+        let name = item.name.to_lowercase();
+        by_id
+            .entry((item.id, name.clone()))
+            .or_default()
+            .push_back(j);
+        by_name.entry(name).or_default().push_back(j);
+    }
+    let mut used = vec![false; b.len()];
+    let mut matched = vec![None; a.len()];
+    for (i, item) in a.iter().enumerate() {
+        if let Some(candidates) = by_id.get_mut(&(item.id, item.name.to_lowercase()))
+            && let Some(j) = candidates.pop_front()
+        {
+            used[j] = true;
+            matched[i] = Some(j);
+        }
+    }
+    for (i, item) in a.iter().enumerate() {
+        if matched[i].is_some() {
+            continue;
+        }
+        if let Some(candidates) = by_name.get_mut(&item.name.to_lowercase()) {
+            while let Some(j) = candidates.pop_front() {
+                if !used[j] {
+                    used[j] = true;
+                    matched[i] = Some(j);
+                    break;
+                }
+            }
+        }
+    }
+    matched
+}
+
+/// 对比两个列表，条目的对应方式见 match_items
+pub fn diff_items(a: &[TodoItem], b: &[TodoItem]) -> ListDiff {
+    let matched = match_items(a, b);
+    let mut in_a = vec![false; b.len()];
+    let mut diff = ListDiff::default();
+    for (item, other) in a.iter().zip(&matched) {
+        match *other {
+            None => diff.only_in_a.push(item.clone()),
+            Some(j) => {
+                in_a[j] = true;
+                let fields = changed_fields(item, &b[j]);
+                if !fields.is_empty() {
+                    diff.changed.push(ChangedItem {
+                        a: item.clone(),
+                        b: b[j].clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+    }
+    // 剩下的是 a 中没有的，按 b 中的原有顺序排列
+    diff.only_in_b = b
+        .iter()
+        .zip(&in_a)
+        .filter(|(_, in_a)| !**in_a)
+        .map(|(item, _)| item.clone())
+        .collect();
+    diff
+}
+
+/// 三方合并中两边的改动无法自动取舍的项；None 表示该侧删除了它
+#[derive(Debug)]
+pub struct SyncConflict {
+    pub local: Option<TodoItem>,
+    pub remote: Option<TodoItem>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncSide {
+    Local,
+    Remote,
+}
+
+impl SyncConflict {
+    pub fn name(&self) -> &str {
+        let item = self.local.as_ref().or(self.remote.as_ref());
+        item.map_or("", |item| item.name.as_str())
+    }
+
+    /// 按选择的一侧给出最终结果，None 表示删除
+    pub fn resolve(&self, side: SyncSide) -> Option<TodoItem> {
+        match side {
+            SyncSide::Local => self.local.clone(),
+            SyncSide::Remote => self
+                .remote
+                .as_ref()
+                .map(|remote| adopt_remote(remote, self.local.as_ref())),
+        }
+    }
+}
+
+/// 三方合并的结果：items 为已确定的条目，conflicts 需要逐个选择后再加入
+#[derive(Debug, Default)]
+pub struct ThreeWayMerge {
+    pub items: Vec<TodoItem>,
+    pub conflicts: Vec<SyncConflict>,
+    pub added: usize,   // 远端新增
+    pub removed: usize, // 远端删除
+    pub updated: usize, // 远端修改
+}
+
+/// 采用远端的版本：id、父任务和手动顺序沿用本地的（本地没有时由 replace_items 分配新 id），
+/// 远端的 id 在本地没有意义
+fn adopt_remote(remote: &TodoItem, local: Option<&TodoItem>) -> TodoItem {
+    let mut item = remote.clone();
+    match local {
+        Some(local) => {
+            item.id = local.id;
+            item.parent = local.parent;
+            item.order = local.order;
+        }
+        None => {
+            item.id = 0;
+            item.parent = None;
+        }
+    }
+    item
+}
+
+impl ThreeWayMerge {
+    /// 合并同一项在三方中的版本，None 表示该方没有这一项
+    fn merge_one(
+        &mut self,
+        base: Option<&TodoItem>,
+        local: Option<&TodoItem>,
+        remote: Option<&TodoItem>,
+    ) {
+        let same = |a: &TodoItem, b: &TodoItem| changed_fields(a, b).is_empty();
+        let conflict = |local: Option<&TodoItem>, remote: Option<&TodoItem>| SyncConflict {
+            local: local.cloned(),
+            remote: remote.cloned(),
+        };
+        match (local, remote) {
+            (Some(l), Some(r)) if same(l, r) => self.items.push(l.clone()),
+            (Some(l), Some(r)) => match base {
+                Some(b) if same(l, b) => {
+                    self.items.push(adopt_remote(r, Some(l)));
+                    self.updated += 1;
+                }
+                Some(b) if same(r, b) => self.items.push(l.clone()),
+                _ => self.conflicts.push(conflict(Some(l), Some(r))),
+            },
+            (Some(l), None) => match base {
+                None => self.items.push(l.clone()), // 本地新增
+                Some(b) if same(l, b) => self.removed += 1,
+                Some(_) => self.conflicts.push(conflict(Some(l), None)),
+            },
+            (None, Some(r)) => match base {
+                None => {
+                    self.items.push(adopt_remote(r, None));
+                    self.added += 1;
+                }
+                Some(b) if same(r, b) => {} // 本地已删除
+                Some(_) => self.conflicts.push(conflict(None, Some(r))),
+            },
+            (None, None) => {}
+        }
+    }
+}
+
+/// 以 base 为共同祖先合并 local 和 remote，条目的对应方式见 match_items。
+/// 只有一侧改动的项采用改动的一侧；一侧删除而另一侧未改动的项删除；
+/// 两侧都改了（或一侧删除、另一侧修改）且结果不同的项记为冲突。
+pub fn merge_three_way(
+    base: &[TodoItem],
+    local: &[TodoItem],
+    remote: &[TodoItem],
+) -> ThreeWayMerge {
+    let local_base = match_items(local, base);
+    let remote_base = match_items(remote, base);
+    let mut base_remote = vec![None; base.len()];
+    for (r, b) in remote_base.iter().enumerate() {
+        if let Some(b) = *b {
+            base_remote[b] = Some(r);
+        }
+    }
+    // 基准中有的项经由基准对应；两侧各自新增的项之间再对应一次
+    let mut local_remote: Vec<Option<usize>> = local_base
+        .iter()
+        .map(|b| b.and_then(|b| base_remote[b]))
+        .collect();
+    let local_new: Vec<usize> = (0..local.len())
+        .filter(|&l| local_base[l].is_none())
+        .collect();
+    let remote_new: Vec<usize> = (0..remote.len())
+        .filter(|&r| remote_base[r].is_none())
+        .collect();
+    let pick = |items: &[TodoItem], indices: &[usize]| -> Vec<TodoItem> {
+        indices.iter().map(|&i| items[i].clone()).collect()
+    };
+    let new_matched = match_items(&pick(local, &local_new), &pick(remote, &remote_new));
+    for (&l, r) in local_new.iter().zip(new_matched) {
+        local_remote[l] = r.map(|r| remote_new[r]);
+    }
+
+    let mut merge = ThreeWayMerge::default();
+    let mut remote_done = vec![false; remote.len()];
+    // 先按本地顺序，再补上远端独有的项
+    for (l, item) in local.iter().enumerate() {
+        if let Some(r) = local_remote[l] {
+            remote_done[r] = true;
+        }
+        merge.merge_one(
+            local_base[l].map(|b| &base[b]),
+            Some(item),
+            local_remote[l].map(|r| &remote[r]),
+        );
+    }
+    for (r, item) in remote.iter().enumerate() {
+        if !remote_done[r] {
+            merge.merge_one(remote_base[r].map(|b| &base[b]), None, Some(item));
+        }
+    }
+    merge
+}
+
+/// 按父子关系排出的显示顺序：父任务在前，子任务紧随其后，附带缩进层级。
+/// 同级保持原有顺序（即排序结果）；父任务不在列表中的条目当作顶层处理，
+/// 手工编辑造成的循环引用也不会丢项。
+pub fn build_tree(items: &[TodoItem]) -> Vec<(usize, &TodoItem)> {
+    let ids: HashSet<u64> = items.iter().map(|item| item.id).collect();
+    let is_root = |item: &TodoItem| item.parent.is_none_or(|parent| !ids.contains(&parent));
+
+    let mut ordered = Vec::with_capacity(items.len());
+    let mut visited = HashSet::new();
+    // 先从顶层任务展开；循环引用中的条目没有根，最后按原顺序补上
+    for root in items.iter().filter(|item| is_root(item)).chain(items) {
+        let mut stack = vec![(0, root)];
+        while let Some((depth, item)) = stack.pop() {
+            if !visited.insert(item.id) {
+                continue;
+            }
+            ordered.push((depth, item));
+            stack.extend(
+                items
+                    .iter()
+                    .filter(|child| child.parent == Some(item.id))
+                    .rev()
+                    .map(|child| (depth + 1, child)),
+            );
+        }
+    }
+    ordered
+}
+
+// 模糊匹配时查询中每个字符平均至少要得到的分数，过滤掉只零散命中几个字母的结果
+const FUZZY_MIN_SCORE_PER_CHAR: i64 = 10;
+
+pub struct TodoList {
+    buffer: Vec<TodoItem>,
+    path: Option<PathBuf>, // 保存时需要路径来原子替换文件；None 表示纯内存列表
+    next_id: u64,
+    dry_run: Option<Vec<TodoItem>>, // 试运行时保存打开时的内容，用于最后列出改动
+    trashed: Vec<TodoItem>,         // 本次删除的项，保存时先追加到回收站文件
+    _lock: Option<fs::File>, // 持有期间其他进程无法打开同一文件，随列表一起释放（在 Drop 保存之后）
+}
+
+impl TodoList {
+    pub fn add_item(&mut self, mut item: TodoItem) -> Result<(), AddError> {
+        check_new_item(&mut item)?;
+        // 名称（忽略大小写）相同即视为重复，冲突项可通过 name_conflict 查询
+        if let Some(conflict) = self.name_conflict(&item.name) {
+            return Err(AddError::Duplicate(conflict.name.clone()));
+        }
+        item.id = self.next_id;
+        self.next_id += 1;
+        self.buffer.push(item);
+        Ok(())
+    }
+
+    /// 直接追加（仍分配新 id），不做重名检查，用于归档这类需要保留历史记录的场景
+    pub fn append_item(&mut self, mut item: TodoItem) {
+        item.id = self.next_id;
+        self.next_id += 1;
+        self.buffer.push(item);
+    }
+
+    /// 加入从其他文件取出的条目（move、merge、archive），返回每一项（保留源文件中的 id）及其结果。
+    /// 源文件的 id 在这里指向无关的项：父任务也一并加入的改挂到父任务的新 id 下，否则作为顶层任务；
+    /// 手动顺序也不沿用。check_duplicates 为 false 时像 append_item 一样不检查重名
+    pub fn add_copied(
+        &mut self,
+        items: Vec<TodoItem>,
+        check_duplicates: bool,
+    ) -> Vec<(TodoItem, Result<(), AddError>)> {
+        let mut new_ids = HashMap::new();
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            let copy = TodoItem {
+                parent: None,
+                order: 0,
+                ..item.clone()
+            };
+            let result = if check_duplicates {
+                self.add_item(copy)
+            } else {
+                self.append_item(copy);
+                Ok(())
+            };
+            if result.is_ok() {
+                new_ids.insert(item.id, self.next_id - 1);
+            }
+            results.push((item, result));
+        }
+        for (item, _) in &results {
+            if let Some(parent) = item.parent
+                && let Some(&new_parent) = new_ids.get(&parent)
+                && let Some(&new_id) = new_ids.get(&item.id)
+                && let Some(copy) = self.buffer.iter_mut().find(|copy| copy.id == new_id)
+            {
+                copy.parent = Some(new_parent);
+            }
+        }
+        results
+    }
+
+    pub fn name_conflict(&self, name: &str) -> Option<&TodoItem> {
+        let name_lower = name.to_lowercase();
+        self.buffer
+            .iter()
+            .find(|item| item.name.to_lowercase() == name_lower)
+    }
+
+    /// 不再关联文件：之后的修改只留在内存中，Drop 时不会写回
+    pub fn detach(&mut self) {
+        self.path = None;
+    }
+
+    pub fn analysis(&self) -> &Vec<TodoItem> {
+        &self.buffer
+    }
+
+    /// 清空并保存，返回删除的条数。配置为不保留备份（backups = 0）时也先把原文件快照为 `.bak`，
+    /// 保证清空总能用 undo 恢复
+    pub fn clear(&mut self) -> Result<usize, Box<dyn Error>> {
+        let removed = self.buffer.len();
+        // 先清空内存中的 buffer（关键！否则 Drop 时会写回旧数据），再写回空列表
+        self.buffer.clear();
+        if let Some(path) = &self.path
+            && removed > 0
+            && self.dry_run.is_none()
+            && BACKUPS.load(Ordering::Relaxed) == 0
+            && path.exists()
+        {
+            rotate_backups(path, 1)?;
+        }
+        self.save_to_file()?;
+        Ok(removed)
+    }
+
+    /// 删除第一个名称完全相同的项，和 del_by_id 一样保存时移入回收站；可能有同名的多项时应改用 del_by_id
+    pub fn del_by_name(&mut self, name: String) -> bool {
+        match self.buffer.iter().find(|item| item.name == name) {
+            Some(item) => self.del_by_id(item.id),
+            None => false,
+        }
+    }
+
+    /// 所有直接或间接挂在 id 下的子任务
+    pub fn descendant_ids(&self, id: u64) -> Vec<u64> {
+        let mut found = Vec::new();
+        let mut pending = vec![id];
+        while let Some(current) = pending.pop() {
+            for item in &self.buffer {
+                if item.parent == Some(current) && item.id != id && !found.contains(&item.id) {
+                    found.push(item.id);
+                    pending.push(item.id);
+                }
+            }
+        }
+        found
+    }
+
+    /// 把 id 的直接子任务提升为顶层任务
+    pub fn detach_children(&mut self, id: u64) {
+        for item in self.buffer.iter_mut() {
+            if item.parent == Some(id) {
+                item.parent = None;
+            }
+        }
+    }
+
+    /// 删除一项，保存时移入回收站（见 trash_path），可用 restore 找回
+    pub fn del_by_id(&mut self, id: u64) -> bool {
+        match self.remove_by_id(id) {
+            Some(item) => {
+                self.trashed.push(item);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 直接移除并返回该项，不进回收站；用于从回收站中取出条目
+    pub fn remove_by_id(&mut self, id: u64) -> Option<TodoItem> {
+        let index = self.buffer.iter().position(|item| item.id == id)?;
+        Some(self.buffer.swap_remove(index))
+    }
+
+    /// 删除带有该标签的所有项，返回删除的数量
+    pub fn del_by_tag(&mut self, tag: &str) -> usize {
+        self.remove_where(|item| item.tags.iter().any(|t| t == tag))
+    }
+
+    /// 删除该优先级的所有项，返回删除的数量
+    pub fn del_by_priority(&mut self, priority: i16) -> usize {
+        self.remove_where(|item| item.priority == priority)
+    }
+
+    /// 删除名称（忽略大小写）包含关键词的所有项，匹配规则与 find_items_by_name 相同，返回删除的数量
+    pub fn del_by_keyword(&mut self, keyword: &str) -> usize {
+        let keyword_lower = keyword.to_lowercase();
+        self.remove_where(|item| item.name.to_lowercase().contains(&keyword_lower))
+    }
+
+    /// 批量删除，删除的项移入回收站；被删项的子任务如果没有一起删除，提升为顶层任务
+    fn remove_where(&mut self, matches: impl Fn(&TodoItem) -> bool) -> usize {
+        let removed: HashSet<u64> = self
+            .buffer
+            .iter()
+            .filter(|item| matches(item))
+            .map(|item| item.id)
+            .collect();
+        let (trashed, kept): (Vec<TodoItem>, Vec<TodoItem>) = self
+            .buffer
+            .drain(..)
+            .partition(|item| removed.contains(&item.id));
+        self.buffer = kept;
+        self.trashed.extend(trashed);
+        for item in &mut self.buffer {
+            if item.parent.is_some_and(|parent| removed.contains(&parent)) {
+                item.parent = None;
+            }
+        }
+        removed.len()
+    }
+
+    /// 整体替换内容；id 为 0 的项分配新 id，指向已不存在的父任务的项提升为顶层任务
+    pub fn replace_items(&mut self, mut items: Vec<TodoItem>) {
+        for item in items.iter_mut().filter(|item| item.id == 0) {
+            item.id = self.next_id;
+            self.next_id += 1;
+        }
+        let ids: HashSet<u64> = items.iter().map(|item| item.id).collect();
+        for item in &mut items {
+            if item.parent.is_some_and(|parent| !ids.contains(&parent)) {
+                item.parent = None;
+            }
+        }
+        self.buffer = items;
+    }
+
+    /// 按 ids 的先后设置手动顺序（1、2、3……），不在 ids 中的项恢复为未排序
+    pub fn set_order(&mut self, ids: &[u64]) {
+        for item in &mut self.buffer {
+            item.order = ids
+                .iter()
+                .position(|&id| id == item.id)
+                .map_or(0, |index| index as u32 + 1);
+        }
+    }
+
+    pub fn edit_item(&mut self, id: u64, changes: ItemChanges) -> bool {
+        match self.buffer.iter_mut().find(|item| item.id == id) {
+            Some(item) => {
+                changes.apply(item);
+                item.updated_at = now_timestamp();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 只修改名称，其他字段不变；新名称与另一项重名（忽略大小写）时拒绝，只改大小写允许。
+    /// 找不到该 id 时返回 Ok(false)
+    pub fn rename(&mut self, id: u64, new_name: &str) -> Result<bool, AddError> {
+        let new_name = validate_name(new_name).map_err(AddError::InvalidName)?;
+        if let Some(conflict) = self.name_conflict(&new_name)
+            && conflict.id != id
+        {
+            return Err(AddError::Duplicate(conflict.name.clone()));
+        }
+        match self.buffer.iter_mut().find(|item| item.id == id) {
+            Some(item) => {
+                item.name = new_name;
+                item.updated_at = now_timestamp();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// 合并重复项：保留优先级最高的一项（相同时优先保留未完成的），其余项不同的内容追加到保留项后面，
+    /// 指向被删除项的子任务改挂到保留项下。返回删除的数量。
+    pub fn dedup(&mut self, key: DedupKey) -> usize {
+        let mut kept: Vec<TodoItem> = Vec::with_capacity(self.buffer.len());
+        let mut index: HashMap<(String, Option<String>), usize> = HashMap::new();
+        let mut replaced: HashMap<u64, u64> = HashMap::new();
+        let mut removed_count = 0;
+        for item in self.buffer.drain(..) {
+            let content_key = match key {
+                DedupKey::Name => None,
+                DedupKey::NameContent => Some(item.content.clone()),
+            };
+            let key = (item.name.to_lowercase(), content_key);
+            let Some(&i) = index.get(&key) else {
+                index.insert(key, kept.len());
+                kept.push(item);
+                continue;
+            };
+            let existing = &mut kept[i];
+            let rank = |item: &TodoItem| (item.priority_key(), !item.completed);
+            let removed = if rank(&item) > rank(existing) {
+                std::mem::replace(existing, item)
+            } else {
+                item
+            };
+            if !removed.content.is_empty() && removed.content != existing.content {
+                if existing.content.is_empty() {
+                    existing.content = removed.content;
+                } else {
+                    existing.content = format!("{}\n{}", existing.content, removed.content);
+                }
+                existing.updated_at = now_timestamp();
+            }
+            // 修复过的文件里可能有相同 id 的重复项，不能映射到自己
+            if removed.id != existing.id {
+                replaced.insert(removed.id, existing.id);
+            }
+            removed_count += 1;
+        }
+
+        for item in kept.iter_mut() {
+            if let Some(mut parent) = item.parent {
+                while let Some(&id) = replaced.get(&parent) {
+                    parent = id;
+                }
+                item.parent = (parent != item.id).then_some(parent);
+            }
+        }
+        self.buffer = kept;
+        removed_count
+    }
+
+    pub fn remove_completed(&mut self) -> usize {
+        let before = self.buffer.len();
+        self.buffer.retain(|item| !item.completed);
+        before - self.buffer.len()
+    }
+
+    /// 同名的未完成项，重复任务完成后同名的旧项已是完成状态
+    pub fn pending_by_name(&self, name: &str) -> Option<&TodoItem> {
+        self.buffer
+            .iter()
+            .find(|item| item.name == name && !item.completed)
+    }
+
+    /// 标记未完成的项为已完成；重复任务会另外生成下一次的待办，截止日期推后一个周期。
+    /// 找不到未完成的该项时返回 Ok(false)；下一次的日期超出范围时报错，列表保持不变
+    pub fn mark_done(&mut self, id: u64) -> Result<bool, String> {
+        let Some(item) = self
+            .buffer
+            .iter_mut()
+            .find(|item| item.id == id && !item.completed)
+        else {
+            return Ok(false);
+        };
+        // 先推算下一次的日期，失败时不做任何修改
+        let next_due = match item.recurrence {
+            Some(recurrence) => {
+                let base = item.due.unwrap_or_else(|| Local::now().date_naive());
+                let due = recurrence.advance(base).ok_or_else(|| {
+                    format!(
+                        "the next occurrence of {} ({} after {}) is out of range",
+                        item.name, recurrence, base
+                    )
+                })?;
+                Some(due)
+            }
+            None => None,
+        };
+        item.completed = true;
+        item.updated_at = now_timestamp();
+
+        if let Some(recurrence) = item.recurrence {
+            let next = TodoItem {
+                due: next_due,
+                tags: item.tags.clone(),
+                url: item.url.clone(),
+                recurrence: Some(recurrence),
+                parent: item.parent,
+                order: item.order,
+                ..TodoItem::new(item.name.clone(), item.content.clone(), item.priority)
+            };
+            self.append_item(next);
+        }
+        Ok(true)
+    }
+
+    pub fn save_to_file(&self) -> Result<(), Box<dyn Error>> {
+        // 没有对应文件的列表（如 Default 构造的）不落盘
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if self.dry_run.is_some() {
+            return Ok(());
+        }
+        let serialized = storage_for(path).serialize(&self.buffer)?;
+
+        // 内容没有变化时不必重写，也不会产生多余的备份
+        if fs::read_to_string(path).is_ok_and(|old| old == serialized) {
+            return Ok(());
+        }
+        if path.exists() {
+            rotate_backups(path, BACKUPS.load(Ordering::Relaxed))?;
+        }
+
+        // 先写入同目录下的临时文件，再 rename 覆盖原文件（同一文件系统内是原子操作），
+        // 进程中途被杀也不会留下写了一半的文件
+        let _saving = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let tmp_path = sibling_path(path, ".tmp");
+        let mut tmp = fs::File::create(&tmp_path)?;
+        tmp.write_all(serialized.as_bytes())?;
+        tmp.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// 保存并关闭列表，保存失败时返回错误（试运行时只列出改动）；之后 Drop 不再重复保存
+    pub fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        let result = self.save_or_report();
+        self.path = None;
+        result
+    }
+
+    fn save_or_report(&self) -> Result<(), Box<dyn Error>> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(original) = &self.dry_run {
+            print_dry_run(path, &describe_changes(original, &self.buffer));
+            return Ok(());
+        }
+        // 先写回收站再保存列表：中途失败时条目最多在两处各有一份，不会丢失
+        if !self.trashed.is_empty() && !is_trash_path(path) {
+            let trash_path = trash_path(path);
+            let mut trash = TodoList::open(&trash_path.to_string_lossy())?;
+            // 回收站里的 id 会重新分配，一起删除的子任务仍挂在父任务下，其余作为顶层任务保存
+            trash.add_copied(self.trashed.clone(), false);
+            trash.commit()?;
+        }
+        self.save_to_file()
+            .map_err(|e| format!("Failed to save {}: {}", path.display(), e).into())
+    }
+
+    /// 优先精确匹配名称，找不到再退回到关键词匹配
+    pub fn resolve_items(&self, name: &str) -> Vec<&TodoItem> {
+        let exact: Vec<&TodoItem> = self
+            .buffer
+            .iter()
+            .filter(|item| item.name == name)
+            .collect();
+        if exact.is_empty() {
+            self.find_items_by_name(name)
+        } else {
+            exact
+        }
+    }
+
+    pub fn find_items_by_name(&self, keyword: &str) -> Vec<&TodoItem> {
+        let keyword_lower = keyword.to_lowercase();
+        self.buffer
+            .iter()
+            // 匹配规则：名称（小写）包含关键词（小写），覆盖更多场景
+            .filter(|item| item.name.to_lowercase().contains(&keyword_lower))
+            .collect()
+    }
+
+    /// 名称完全相同（忽略大小写）的项；重复任务完成后可能有多项同名
+    pub fn find_items_by_exact_name(&self, name: &str) -> Vec<&TodoItem> {
+        let name_lower = name.to_lowercase();
+        self.buffer
+            .iter()
+            .filter(|item| item.name.to_lowercase() == name_lower)
+            .collect()
+    }
+
+    /// 与 find_items_by_name 相同，但不做大小写转换
+    pub fn find_items_by_name_cased(&self, keyword: &str) -> Vec<&TodoItem> {
+        self.buffer
+            .iter()
+            .filter(|item| item.name.contains(keyword))
+            .collect()
+    }
+
+    pub fn find_items_by_regex(&self, pattern: &str) -> Result<Vec<&TodoItem>, regex::Error> {
+        let re = Regex::new(pattern)?;
+        Ok(self
+            .buffer
+            .iter()
+            .filter(|item| re.is_match(&item.name))
+            .collect())
+    }
+
+    /// 按名称模糊匹配，返回条目及得分，得分从高到低；得分过低的不算命中
+    pub fn fuzzy_find(&self, query: &str) -> Vec<(&TodoItem, i64)> {
+        let matcher = SkimMatcherV2::default();
+        let min_score = FUZZY_MIN_SCORE_PER_CHAR * query.chars().count() as i64;
+        let mut found: Vec<(&TodoItem, i64)> = self
+            .buffer
+            .iter()
+            .filter_map(|item| {
+                matcher
+                    .fuzzy_match(&item.name, query)
+                    .filter(|score| *score >= min_score)
+                    .map(|score| (item, score))
+            })
+            .collect();
+        found.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+        found
+    }
+
+    pub fn find_items_by_content(&self, keyword: &str) -> Vec<&TodoItem> {
+        let keyword_lower = keyword.to_lowercase();
+        self.buffer
+            .iter()
+            .filter(|item| item.content.to_lowercase().contains(&keyword_lower))
+            .collect()
+    }
+
+    pub fn find_items(&self, keyword: &str) -> Vec<&TodoItem> {
+        let keyword_lower = keyword.to_lowercase();
+        self.buffer
+            .iter()
+            // 名称或内容任一命中即可，每项只会出现一次
+            .filter(|item| {
+                item.name.to_lowercase().contains(&keyword_lower)
+                    || item.content.to_lowercase().contains(&keyword_lower)
+            })
+            .collect()
+    }
+
+    pub fn open(value: &str) -> Result<Self, Box<dyn Error>> {
+        // 试运行不会写回，既不加锁也不创建文件
+        let dry_run = DRY_RUN.load(Ordering::Relaxed);
+        let lock = if dry_run {
+            None
+        } else {
+            ensure_parent_dir(Path::new(value)).map_err(|e| format!("无法创建目录: {}", e))?;
+            Some(lock_file(Path::new(value))?)
+        };
+        // 打开文件（只读、可写、不存在则创建）
+        let file = OpenOptions::new()
+            .read(true)
+            .write(!dry_run)
+            .create(!dry_run)
+            .truncate(false)
+            .open(value);
+        let buffer = match file {
+            Ok(mut file) => {
+                // 确保文件指针在开头
+                file.rewind()?;
+
+                storage_for(Path::new(value))
+                    .deserialize(&mut BufReader::new(file))
+                    .map_err(|e| CorruptFile::new(Path::new(value), e))?
+            }
+            Err(e) if dry_run && e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("无法打开文件: {}", e).into()), // 更明确的错误提示
+        };
+        Ok(Self::with_items(buffer, value, lock))
+    }
+
+    /// 不加锁读取文件的当前内容，得到的列表不关联文件，文件不存在时为空列表。
+    /// 保存是整体替换文件，因此不会读到写了一半的内容。
+    pub fn snapshot(value: &str) -> Result<Self, Box<dyn Error>> {
+        let buffer = match fs::File::open(value) {
+            Ok(file) => storage_for(Path::new(value))
+                .deserialize(&mut BufReader::new(file))
+                .map_err(|e| CorruptFile::new(Path::new(value), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("无法打开文件: {}", e).into()),
+        };
+        let mut todo_list = Self::with_items(buffer, value, None);
+        todo_list.detach();
+        Ok(todo_list)
+    }
+
+    /// 用已读出的条目构造关联到文件的列表
+    pub fn with_items(mut buffer: Vec<TodoItem>, value: &str, lock: Option<fs::File>) -> Self {
+        // 旧版本文件没有 id，按顺序补齐，保证每一项都有唯一 id
+        let mut next_id = buffer.iter().map(|item| item.id).max().unwrap_or(0) + 1;
+        for item in buffer.iter_mut().filter(|item| item.id == 0) {
+            item.id = next_id;
+            next_id += 1;
+        }
+        // 旧格式的条目在这里转换（目前各版本字段都兼容，只需更新版本号），保存时写回新版本；
+        // 更新版本写入的条目保持原样
+        for item in buffer.iter_mut() {
+            item.schema_version = item.schema_version.max(SCHEMA_VERSION);
+        }
+        let dry_run = DRY_RUN.load(Ordering::Relaxed).then(|| buffer.clone());
+
+        TodoList {
+            buffer,
+            path: Some(PathBuf::from(value)),
+            next_id,
+            dry_run,
+            trashed: Vec::new(),
+            _lock: lock,
+        }
+    }
+
+    /// 新增条目的快速路径：逐行格式的文件只需扫描一遍查重并取得最大 id，
+    /// 然后把新条目追加为一行，不必重新序列化整个列表。
+    /// 旧的 JSON 数组格式会退回到完整读写，并顺带转换为逐行格式；TOML 文件同样完整读写。
+    pub fn append_to_file(
+        value: &str,
+        mut item: TodoItem,
+    ) -> Result<AppendOutcome, Box<dyn Error>> {
+        if let Err(e) = check_new_item(&mut item) {
+            return Ok(AppendOutcome::Rejected(e));
+        }
+        let path = Path::new(value);
+        let layout = match fs::File::open(path) {
+            Ok(file) => detect_layout(&mut BufReader::new(file))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => StorageLayout::Empty,
+            Err(e) => return Err(format!("无法打开文件: {}", e).into()),
+        };
+        // 只有逐行格式能直接追加，其余情况（包括配置了格式化输出）读入整个列表再保存
+        if is_toml_path(path)
+            || matches!(layout, StorageLayout::Array)
+            || PRETTY_JSON.load(Ordering::Relaxed)
+        {
+            let mut todo_list = Self::open(value)?;
+            if let Some(parent) = item.parent
+                && !todo_list
+                    .buffer
+                    .iter()
+                    .any(|existing| existing.id == parent)
+            {
+                return Ok(AppendOutcome::MissingParent(parent));
+            }
+            match todo_list.add_item(item.clone()) {
+                Ok(()) => {}
+                Err(AddError::Duplicate(_)) => {
+                    let conflict = todo_list.name_conflict(&item.name).cloned();
+                    return Ok(AppendOutcome::Duplicate(conflict.unwrap_or(item)));
+                }
+                Err(e) => return Ok(AppendOutcome::Rejected(e)),
+            }
+            let added = todo_list.buffer.last().cloned().unwrap_or(item);
+            todo_list.commit()?;
+            return Ok(AppendOutcome::Added(added));
+        }
+
+        // 试运行只读取文件，不创建目录和锁文件
+        let dry_run = DRY_RUN.load(Ordering::Relaxed);
+        let _lock = if dry_run {
+            None
+        } else {
+            ensure_parent_dir(path).map_err(|e| format!("无法创建目录: {}", e))?;
+            Some(lock_file(path)?)
+        };
+        let name_lower = item.name.to_lowercase();
+        let mut max_id = 0;
+        let mut parent_found = item.parent.is_none();
+        if let Ok(file) = fs::File::open(path) {
+            let heads =
+                serde_json::Deserializer::from_reader(BufReader::new(file)).into_iter::<ItemHead>();
+            for (position, head) in heads.enumerate() {
+                let head = head.map_err(|e| CorruptFile::new(path, e))?;
+                if head.name.to_lowercase() == name_lower {
+                    return Ok(AppendOutcome::Duplicate(read_item_at(path, position)?));
+                }
+                max_id = max_id.max(head.id);
+                parent_found |= item.parent == Some(head.id);
+            }
+        }
+        if let Some(parent) = item.parent
+            && !parent_found
+        {
+            return Ok(AppendOutcome::MissingParent(parent));
+        }
+        item.id = max_id + 1;
+        if dry_run {
+            print_dry_run(path, &[format!("+ {}", item.summary())]);
+            return Ok(AppendOutcome::Added(item));
+        }
+
+        let existed = path.exists();
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let mut line = serde_json::to_string(&item)?;
+        line.push('\n');
+        // 文件末尾没有换行（如手工编辑过）时先补一个，保证每项独占一行
+        let before = file.metadata()?.len();
+        if before > 0 {
+            let mut last = [0u8];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                line.insert(0, '\n');
+            }
+        }
+        // 追加只记录原来的长度，不拷贝整个文件；backups = 0 时完全没有额外开销
+        if existed {
+            let after = before + line.len() as u64;
+            record_append(path, BACKUPS.load(Ordering::Relaxed), before, after)?;
+        }
+        let _saving = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())?;
+        file.sync_all()?;
+        Ok(AppendOutcome::Added(item))
+    }
+}
+
+/// 默认构造得到的是不关联任何文件的空列表，既不会读取也不会覆盖用户已有的待办文件
+impl Default for TodoList {
+    fn default() -> Self {
+        TodoList {
+            buffer: Vec::new(),
+            path: None,
+            next_id: 1,
+            dry_run: None,
+            trashed: Vec::new(),
+            _lock: None,
+        }
+    }
+}
+
+// Drop 时的保存只是兜底：出错只能打印，调用方无从得知，修改文件的命令应显式调用 commit
+impl Drop for TodoList {
+    fn drop(&mut self) {
+        if let Err(e) = self.save_or_report() {
+            eprintln!("保存文件失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(id: u64, name: &str) -> TodoItem {
+        TodoItem {
+            id,
+            ..TodoItem::new(name.to_string(), String::new(), 0i16)
+        }
+    }
+
+    /// 每个测试使用独立的临时目录，返回其中的待办文件路径
+    fn temp_todo(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("todo_cli_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("list.todo")
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn parse_recurrence_accepts_keywords_and_intervals() {
+        assert_eq!(parse_recurrence("daily"), Ok(Recurrence::Days(1)));
+        assert_eq!(parse_recurrence(" Weekly "), Ok(Recurrence::Weeks(1)));
+        assert_eq!(parse_recurrence("every:2W"), Ok(Recurrence::Weeks(2)));
+        assert_eq!(
+            parse_recurrence("every:1000m"),
+            Ok(Recurrence::Months(1000))
+        );
+        for invalid in [
+            "hourly",
+            "every:",
+            "every:0d",
+            "every:3x",
+            "every:-1d",
+            "every:1001d",
+        ] {
+            assert!(parse_recurrence(invalid).is_err(), "{}", invalid);
+        }
+        // 显示形式可以再解析回来
+        for recurrence in [Recurrence::Months(1), Recurrence::Days(3)] {
+            assert_eq!(parse_recurrence(&recurrence.to_string()), Ok(recurrence));
+        }
+    }
+
+    #[test]
+    fn advance_moves_by_one_period() {
+        assert_eq!(
+            Recurrence::Weeks(2).advance(date(2026, 1, 1)),
+            Some(date(2026, 1, 15))
+        );
+        // 月末推到较短的月份时落在该月最后一天
+        assert_eq!(
+            Recurrence::Months(1).advance(date(2026, 1, 31)),
+            Some(date(2026, 2, 28))
+        );
+        assert_eq!(Recurrence::Days(1).advance(NaiveDate::MAX), None);
+        assert_eq!(Recurrence::Months(1000).advance(NaiveDate::MAX), None);
+    }
+
+    #[test]
+    fn mark_done_on_overflow_leaves_the_list_unchanged() {
+        let mut list = TodoList::default();
+        list.append_item(TodoItem {
+            due: Some(NaiveDate::MAX),
+            recurrence: Some(Recurrence::Days(1)),
+            ..item(0, "forever")
+        });
+        assert!(list.mark_done(1).is_err());
+        assert_eq!(list.analysis().len(), 1);
+        assert!(!list.analysis()[0].completed);
+    }
+
+    #[test]
+    fn mark_done_schedules_the_next_occurrence() {
+        let mut list = TodoList::default();
+        list.append_item(TodoItem {
+            due: Some(date(2026, 1, 31)),
+            recurrence: Some(Recurrence::Months(1)),
+            ..item(0, "rent")
+        });
+        assert_eq!(list.mark_done(1), Ok(true));
+        assert_eq!(list.mark_done(1), Ok(false));
+        let items = list.analysis();
+        assert_eq!(items.len(), 2);
+        assert!(items[0].completed);
+        assert_eq!((items[1].id, items[1].completed), (2, false));
+        assert_eq!(items[1].due, Some(date(2026, 2, 28)));
+    }
+
+    #[test]
+    fn merge_three_way_takes_one_sided_changes_and_reports_conflicts() {
+        let base = vec![item(1, "a"), item(2, "b"), item(3, "c"), item(4, "e")];
+        let edited = |item: &TodoItem, content: &str| TodoItem {
+            content: content.to_string(),
+            ..item.clone()
+        };
+        // 本地：改了 a，删了 c，e 改成 local
+        let local = vec![
+            edited(&base[0], "local"),
+            base[1].clone(),
+            edited(&base[3], "local"),
+        ];
+        // 远端：改了 b，新增 d，e 改成 remote
+        let remote = vec![
+            base[0].clone(),
+            edited(&base[1], "remote"),
+            base[2].clone(),
+            edited(&base[3], "remote"),
+            item(7, "d"),
+        ];
+        let merge = merge_three_way(&base, &local, &remote);
+
+        let merged: Vec<(&str, &str)> = merge
+            .items
+            .iter()
+            .map(|item| (item.name.as_str(), item.content.as_str()))
+            .collect();
+        assert_eq!(merged, [("a", "local"), ("b", "remote"), ("d", "")]);
+        // 采用远端版本时沿用本地 id，远端新增的项等待重新分配
+        assert_eq!(merge.items[1].id, 2);
+        assert_eq!(merge.items[2].id, 0);
+        assert_eq!((merge.added, merge.updated, merge.removed), (1, 1, 0));
+        assert_eq!(merge.conflicts.len(), 1);
+        assert_eq!(merge.conflicts[0].name(), "e");
+        assert_eq!(
+            merge.conflicts[0]
+                .resolve(SyncSide::Remote)
+                .unwrap()
+                .content,
+            "remote"
+        );
+    }
+
+    #[test]
+    fn merge_three_way_keeps_items_with_the_same_name() {
+        // 重复任务完成后，已完成的旧项和新的待办项同名
+        let chores = vec![
+            TodoItem {
+                completed: true,
+                ..item(1, "chore")
+            },
+            item(2, "chore"),
+        ];
+        let merge = merge_three_way(&chores, &chores, &chores);
+        let ids: Vec<u64> = merge.items.iter().map(|item| item.id).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!((merge.added, merge.updated, merge.removed), (0, 0, 0));
+        assert!(merge.conflicts.is_empty());
+
+        // 远端完成了新的一项：只更新这一项，已完成的旧项保留
+        let mut remote = chores.clone();
+        remote[1].completed = true;
+        let merge = merge_three_way(&chores, &chores, &remote);
+        let items: Vec<(u64, bool)> = merge
+            .items
+            .iter()
+            .map(|item| (item.id, item.completed))
+            .collect();
+        assert_eq!(items, [(1, true), (2, true)]);
+        assert_eq!(merge.updated, 1);
+
+        // 不同文件中 id 不一致时按名称依次对应，多出来的同名项不会丢
+        let renumbered = vec![item(7, "chore"), item(8, "chore"), item(9, "chore")];
+        let diff = diff_items(&chores, &renumbered);
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.only_in_a.is_empty());
+        assert_eq!(diff.only_in_b.len(), 1);
+        assert_eq!(diff.only_in_b[0].id, 9);
+    }
+
+    #[test]
+    fn repair_items_skips_damaged_lines() {
+        let text = "{\"id\":1,\"name\":\"a\",\"content\":\"\",\"priority\":0}\n\
+                    {\"id\":2,\"name\":\"b\",\"con\n\
+                    \n\
+                    {\"id\":3,\"name\":\"c\",\"content\":\"\",\"priority\":0}\n";
+        let (items, dropped) = repair_items(text);
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["a", "c"]);
+        assert_eq!(dropped, 1);
+    }
+
+    #[test]
+    fn repair_items_keeps_the_intact_prefix_of_an_array() {
+        let text = "[{\"name\":\"a\",\"content\":\"\",\"priority\":0},\n \
+                    {\"name\":\"b\",\"content\":\"\",\"priority\":0},\n \
+                    {\"name\":\"c\",\"cont";
+        let (items, dropped) = repair_items(text);
+        let names: Vec<&str> = items.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(dropped, 1);
+    }
+
+    // GitHub issues 接口返回的数组，节选了用到的字段；第二项是 pull request
+    const GITHUB_ISSUES: &str = r#"[
+        {"number": 1, "title": " Fix login ", "body": "steps\r\nto reproduce", "state": "open",
+         "labels": [{"name": "Bug"}, "ui"]},
+        {"number": 2, "title": "Add login tests", "body": null, "state": "open", "labels": [],
+         "pull_request": {"url": "https://api.github.com/repos/o/r/pulls/2", "merged_at": null}},
+        {"number": 3, "title": "Old crash", "body": "", "state": "closed", "labels": []}
+    ]"#;
+
+    #[test]
+    fn from_github_issues_skips_pull_requests() {
+        let items = from_github_issues(GITHUB_ISSUES.as_bytes(), 0, false).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].name, "Fix login");
+        assert_eq!(items[0].content, "steps\nto reproduce");
+        assert_eq!(items[0].tags, ["bug", "ui"]);
+
+        let items = from_github_issues(GITHUB_ISSUES.as_bytes(), 0, true).unwrap();
+        let names: Vec<(&str, bool)> = items
+            .iter()
+            .map(|item| (item.name.as_str(), item.completed))
+            .collect();
+        assert_eq!(names, [("Fix login", false), ("Old crash", true)]);
+    }
+
+    #[test]
+    fn to_markdown_renders_one_task_per_line() {
+        let items = vec![
+            TodoItem {
+                completed: true,
+                priority: 3,
+                content: "first line\nsecond line".to_string(),
+                ..item(1, "write")
+            },
+            item(2, "read"),
+        ];
+        assert_eq!(
+            to_markdown(&items),
+            "- [x] write (priority 3): first line second line\n- [ ] read (priority 0): \n"
+        );
+    }
+
+    #[test]
+    fn build_tree_nests_children_and_keeps_orphans_and_cycles() {
+        let child = |id, name, parent| TodoItem {
+            parent: Some(parent),
+            ..item(id, name)
+        };
+        let items = vec![
+            item(1, "a"),
+            child(2, "b", 1),
+            child(3, "orphan", 99),
+            child(4, "d", 2),
+            child(5, "x", 6),
+            child(6, "y", 5),
+        ];
+        let tree: Vec<(usize, &str)> = build_tree(&items)
+            .into_iter()
+            .map(|(depth, item)| (depth, item.name.as_str()))
+            .collect();
+        assert_eq!(
+            tree,
+            [
+                (0, "a"),
+                (1, "b"),
+                (2, "d"),
+                (0, "orphan"),
+                (0, "x"),
+                (1, "y")
+            ]
+        );
+    }
+
+    #[test]
+    fn descendant_ids_follow_nested_children() {
+        let mut list = TodoList::default();
+        list.append_item(item(0, "a"));
+        list.append_item(TodoItem {
+            parent: Some(1),
+            ..item(0, "b")
+        });
+        list.append_item(TodoItem {
+            parent: Some(2),
+            ..item(0, "c")
+        });
+        list.append_item(item(0, "d"));
+        assert_eq!(list.descendant_ids(1), [2, 3]);
+        assert!(list.descendant_ids(4).is_empty());
+    }
+
+    #[test]
+    fn remove_by_id_picks_the_right_item_among_duplicate_names() {
+        let mut list = TodoList::default();
+        list.append_item(TodoItem {
+            completed: true,
+            ..item(0, "chore")
+        });
+        list.append_item(item(0, "chore"));
+
+        let removed = list.remove_by_id(2).unwrap();
+        assert!(!removed.completed);
+        assert_eq!(list.analysis().len(), 1);
+        assert_eq!(list.analysis()[0].id, 1);
+        assert!(list.remove_by_id(2).is_none());
+    }
+
+    #[test]
+    fn add_copied_relinks_parents_within_the_copied_items() {
+        let mut list = TodoList::default();
+        list.append_item(item(0, "existing"));
+        list.append_item(item(0, "other"));
+        // 源文件中 sub 挂在 id 2 下，orphan 的父任务没有一起复制
+        let copied = vec![
+            TodoItem {
+                parent: Some(1),
+                order: 5,
+                ..item(2, "sub")
+            },
+            item(1, "parent"),
+            TodoItem {
+                parent: Some(7),
+                ..item(3, "orphan")
+            },
+            item(4, "Existing"),
+        ];
+        let results = list.add_copied(copied, true);
+        let added: Vec<u64> = results
+            .iter()
+            .filter(|(_, result)| result.is_ok())
+            .map(|(item, _)| item.id)
+            .collect();
+        assert_eq!(added, [2, 1, 3]);
+        assert!(matches!(results[3].1, Err(AddError::Duplicate(_))));
+
+        let find = |name: &str| {
+            list.analysis()
+                .iter()
+                .find(|item| item.name == name)
+                .unwrap()
+        };
+        assert_eq!(find("sub").parent, Some(find("parent").id));
+        assert_eq!(find("sub").order, 0);
+        assert_eq!(find("orphan").parent, None);
+        assert_eq!(find("other").id, 2);
+    }
+
+    #[test]
+    fn add_item_stores_the_trimmed_name() {
+        let mut list = TodoList::default();
+        assert!(list.add_item(item(0, "  spaced  ")).is_ok());
+        assert_eq!(list.analysis()[0].name, "spaced");
+        assert!(matches!(
+            list.add_item(item(0, " Spaced")),
+            Err(AddError::Duplicate(_))
+        ));
+        assert!(matches!(
+            list.add_item(item(0, "   ")),
+            Err(AddError::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn dedup_prefers_the_pending_item_when_priorities_tie() {
+        let mut list = TodoList::default();
+        list.append_item(TodoItem {
+            completed: true,
+            content: "old".to_string(),
+            ..item(0, "chore")
+        });
+        list.append_item(item(0, "Chore"));
+        list.append_item(TodoItem {
+            priority: 5,
+            completed: true,
+            ..item(0, "report")
+        });
+        list.append_item(item(0, "report"));
+
+        assert_eq!(list.dedup(DedupKey::Name), 2);
+        let kept: Vec<(u64, bool, &str)> = list
+            .analysis()
+            .iter()
+            .map(|item| (item.id, item.completed, item.content.as_str()))
+            .collect();
+        // 优先级更高的仍然优先，即使已经完成
+        assert_eq!(kept, [(2, false, "old"), (3, true, "")]);
+    }
+
+    #[test]
+    fn del_by_name_removes_only_the_first_exact_match() {
+        let mut list = TodoList::default();
+        list.append_item(item(0, "chore"));
+        list.append_item(item(0, "chore"));
+        list.append_item(item(0, "Chore"));
+
+        assert!(list.del_by_name("chore".to_string()));
+        let mut ids: Vec<u64> = list.analysis().iter().map(|item| item.id).collect();
+        ids.sort();
+        assert_eq!(ids, [2, 3]);
+        // 和其他删除一样进回收站
+        let trashed: Vec<u64> = list.trashed.iter().map(|item| item.id).collect();
+        assert_eq!(trashed, [1]);
+        assert!(!list.del_by_name("missing".to_string()));
+    }
+
+    #[test]
+    fn append_migrates_a_legacy_array_and_then_appends_lines() {
+        let path = temp_todo("append");
+        let value = path.to_str().unwrap();
+        fs::write(
+            &path,
+            "[\n  {\"name\": \"a\", \"content\": \"\", \"priority\": 1},\n  \
+             {\"name\": \"b\", \"content\": \"\", \"priority\": 2}\n]",
+        )
+        .unwrap();
+
+        // 数组格式整体读写，顺带转换为逐行格式并补齐 id
+        let added = TodoList::append_to_file(value, item(0, "c")).unwrap();
+        assert!(matches!(added, AppendOutcome::Added(ref item) if item.id == 3));
+        let text = fs::read_to_string(&path).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        assert!(text.lines().all(|line| line.starts_with('{')));
+
+        // 逐行格式直接追加一行
+        let added = TodoList::append_to_file(value, item(0, "d")).unwrap();
+        assert!(matches!(added, AppendOutcome::Added(ref item) if item.id == 4));
+        let appended = fs::read_to_string(&path).unwrap();
+        assert!(appended.starts_with(&text));
+        assert!(appended[text.len()..].contains("\"name\":\"d\""));
+
+        // 重名时给出完整的冲突项
+        match TodoList::append_to_file(value, item(0, "B")).unwrap() {
+            AppendOutcome::Duplicate(conflict) => {
+                assert_eq!((conflict.id, conflict.priority), (2, 2))
+            }
+            _ => panic!("expected a duplicate"),
+        }
+        match TodoList::append_to_file(
+            value,
+            TodoItem {
+                parent: Some(9),
+                ..item(0, "e")
+            },
+        )
+        .unwrap()
+        {
+            AppendOutcome::MissingParent(parent) => assert_eq!(parent, 9),
+            _ => panic!("expected a missing parent"),
+        }
+
+        let list = TodoList::snapshot(value).unwrap();
+        let items: Vec<(u64, &str)> = list
+            .analysis()
+            .iter()
+            .map(|item| (item.id, item.name.as_str()))
+            .collect();
+        assert_eq!(items, [(1, "a"), (2, "b"), (3, "c"), (4, "d")]);
+
+        // 撤销追加只截断新增的一行，再撤销回到数组格式的原文件
+        assert!(restore_backup(&path).unwrap());
+        assert_eq!(TodoList::snapshot(value).unwrap().analysis().len(), 3);
+        assert!(restore_backup(&path).unwrap());
+        assert!(fs::read_to_string(&path).unwrap().starts_with('['));
+
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -1,251 +1,910 @@
-use clap::Parser;
-use dirs::data_dir;
-use property::Property;
+use chrono::{Local, NaiveDate};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use crossterm::ExecutableCommand;
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{Clear, ClearType, disable_raw_mode, enable_raw_mode};
+use dirs::{config_dir, data_dir, home_dir, state_dir};
+use notify::{RecursiveMode, Watcher};
+use owo_colors::{OwoColorize, Stream};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
-use std::fs::OpenOptions;
-use std::hash::{DefaultHasher, Hash, Hasher};
-use std::io::prelude::*;
-use std::io::{Read, Write};
-use std::path::Path;
-use std::process::exit;
-use std::sync::{Arc, Mutex};
+use std::hash::{Hash, Hasher};
+use std::io::{BufReader, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, mpsc};
 use std::thread::JoinHandle;
+use std::time::Duration;
 use std::{fs, io};
 use ter_menu::TerminalDropDown;
+use todo_cli::{
+    AddError, AppendOutcome, BACKUPS, CorruptFile, DEFAULT_BACKUPS, DRY_RUN, DedupKey, ItemChanges,
+    PRETTY_JSON, Priority, Recurrence, SAVE_LOCK, SCHEMA_VERSION, SortField, Storage, SyncSide,
+    TodoItem, TodoList, TomlStorage, build_tree, compute_stats, count_pending, diff_items,
+    find_by_name_indexed, from_csv, from_github_issues, from_taskwarrior, humanize_due, is_backlog,
+    is_toml_path, item_schema, lock_file, merge_three_way, normalize_tags, parse_bulk_lines,
+    parse_date, parse_priority, parse_recurrence, parse_url, priority_style, remove_with_backups,
+    repair_items, restore_backup, sibling_path, sort_items, to_csv, to_markdown, to_taskwarrior,
+    trash_path, validate_name,
+};
 
-fn get_default_path() -> String {
-    data_dir()
-        .unwrap()
-        .join("todo.todo")
-        .to_str()
-        .unwrap()
-        .to_string()
+/// 配置文件 `~/.config/todo/config.toml` 中可设置的默认值，命令行参数优先于这里的值
+#[derive(Debug, Default, Deserialize)]
+struct Config {
+    path: Option<String>,
+    priority: Option<i16>,
+    sort: Option<SortField>,
+    backups: Option<usize>, // 保留的备份份数，0 表示不备份
+    pretty: Option<bool>,   // 把 .todo 写成格式化的 JSON 数组
+    #[serde(default)]
+    profiles: BTreeMap<String, String>, // 配置名 -> 待办文件路径，供 --profile 使用
+    #[serde(default)]
+    templates: BTreeMap<String, Template>, // 模板名 -> 预填的字段，供 add --template 使用
 }
 
-fn calculate_hash<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s); // 将值的哈希写入哈希器
-    s.finish() // 获取最终哈希值（u64）
+const CONFIG_KEYS: [&str; 7] = [
+    "path",
+    "priority",
+    "sort",
+    "backups",
+    "pretty",
+    "profiles",
+    "templates",
+];
+
+/// `[templates.<名称>]` 表：add --template 时预填，命令行给出的值优先
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+struct Template {
+    #[serde(default)]
+    content: String,
+    priority: Option<i16>,
+    #[serde(default)]
+    tags: Vec<String>,
 }
 
-fn fix(path: String) -> String {
-    let path = Path::new(&path);
-    if path.is_dir() || !path.extension().map_or(false, |ext| ext == "todo") {
-        let mut new_path = path.to_path_buf();
-        if new_path.is_dir() {
-            new_path.push("todo.todo");
-        } else {
-            new_path.set_extension("todo");
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("todo").join("config.toml"))
+}
+
+/// 宽松地读取配置：文件不存在不算错误，格式错误或未知字段只警告不中断
+fn load_config() -> Config {
+    let Some(path) = config_path() else {
+        return Config::default();
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Config::default(),
+        Err(e) => {
+            eprintln!("Warning: failed to read config {}: {}", path.display(), e);
+            return Config::default();
         }
-        new_path
-            .to_str()
-            .unwrap_or_else(|| {
-                eprintln!("The path is not allowed.");
-                exit(1);
-            })
-            .to_string()
-    } else {
-        path.to_str()
-            .unwrap_or_else(|| {
-                eprintln!("The path is not allowed.");
-                exit(1);
-            })
-            .to_string()
+    };
+    let table = match content.parse::<toml::Table>() {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("Warning: invalid config {}: {}", path.display(), e);
+            return Config::default();
+        }
+    };
+    for key in table
+        .keys()
+        .filter(|key| !CONFIG_KEYS.contains(&key.as_str()))
+    {
+        eprintln!(
+            "Warning: unknown config key '{}' in {}",
+            key,
+            path.display()
+        );
     }
+    table.try_into().unwrap_or_else(|e| {
+        eprintln!("Warning: invalid config {}: {}", path.display(), e);
+        Config::default()
+    })
 }
 
-fn exit_when_refuse() {
-    let stdin = io::stdin();
-    print!("Are you sure?(y/N)");
-    io::stdout().flush().unwrap();
-    let mut buffer = String::new();
-    stdin.read_line(&mut buffer).unwrap();
-    if buffer.to_lowercase().trim() != "y" {
-        println!("Canceled.");
-        exit(0);
+// 由全局参数 --quiet 设置，用 info! 输出的提示信息不再显示（错误仍输出到 stderr）
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// 输出提示性信息，--quiet 时省略；列表、查询结果等数据输出仍用 println!
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+// 由全局参数 --yes 设置，所有确认提示直接视为回答 y
+static YES: AtomicBool = AtomicBool::new(false);
+
+fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(load_config)
+}
+
+/// --version 同时给出存储格式版本，外部工具可据此判断兼容性
+fn version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| {
+        format!(
+            "{} (storage schema {})",
+            env!("CARGO_PKG_VERSION"),
+            SCHEMA_VERSION
+        )
+    })
+}
+
+fn default_priority() -> i16 {
+    config().priority.unwrap_or(0)
+}
+
+fn default_sort() -> SortField {
+    config().sort.unwrap_or(SortField::Priority)
+}
+
+// 由全局参数 --profile 设置，为已解析出的路径；优先于环境变量和配置中的默认路径
+static PROFILE_PATH: OnceLock<String> = OnceLock::new();
+
+/// 配置中的路径可以用 `~/` 开头表示主目录
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+        _ => path.to_string(),
     }
 }
 
-fn open_todo_list(path: String) -> TodoList {
-    let path = fix(path);
-    TodoList::open_without_doubt(path.as_str())
+fn profile_path(name: &str) -> Result<String, Box<dyn Error>> {
+    config()
+        .profiles
+        .get(name)
+        .map(|path| expand_home(path))
+        .ok_or_else(|| format!("unknown profile '{}' (see `todo profiles`).", name).into())
 }
 
-#[derive(Parser, Debug)]
-#[command(name = "Todo", version, about, long_about = None)]
-enum Command {
-    Add {
-        #[arg(short, long, default_value_t = String::from("Untitled"))]
-        name: String,
-        #[arg(short, long, default_value_t = 0)]
-        priority: i16, // 优先级
-        #[arg(long, default_value_t = get_default_path())]
-        path: String,
-        content: String,
-    },
-    View {
-        #[arg(long, default_value_t = get_default_path())]
-        path: String,
-    },
-    Find {
-        #[arg(long, default_value_t = get_default_path())]
-        path: String,
+fn get_default_path() -> String {
+    default_path_with_source().0
+}
 
-        name: String,
-    },
-    Clear {
-        #[arg(long, default_value_t = get_default_path())]
-        path: String,
-    },
-    Delete {
-        #[arg(long, default_value_t = get_default_path())]
-        path: String,
+/// 默认路径及其来源（供 where 显示）
+fn default_path_with_source() -> (String, &'static str) {
+    if let Some(path) = PROFILE_PATH.get() {
+        return (path.clone(), "--profile");
+    }
+    // 环境变量优先，其次是配置文件，同样会经过 fix 规范化
+    if let Ok(path) = std::env::var("TODO_PATH")
+        && !path.trim().is_empty()
+    {
+        return (path, "TODO_PATH");
+    }
+    if let Some(path) = &config().path {
+        return (path.clone(), "config");
+    }
+    // XDG_DATA_HOME 只接受绝对路径（与规范一致）；无法确定数据目录时退回到当前目录
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(data_dir)
+        .unwrap_or_default();
+    let path = data_home.join("todo.todo").to_string_lossy().into_owned();
+    (path, "default")
+}
 
-        name: String,
-    },
+fn fix(path: String) -> Result<String, Box<dyn Error>> {
+    let path = Path::new(&path);
+    let mut new_path = path.to_path_buf();
+    if path.is_dir() {
+        new_path.push("todo.todo");
+    } else if path.extension().is_none_or(|ext| ext != "todo") && !is_toml_path(path) {
+        new_path.set_extension("todo");
+    }
+    canonical(&new_path)?
+        .to_str()
+        .map(str::to_string)
+        .ok_or_else(|| "The path is not allowed.".into())
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone, Eq, PartialEq, Hash, Property)]
-struct TodoItem {
-    name: String,
-    content: String,
-    priority: i16,
+/// 转成绝对路径，使同一文件的不同写法（相对路径、`./`、符号链接）得到同一个结果，
+/// 锁文件和备份也就落在同一处。文件已存在时解析符号链接（保存时替换的是链接指向的文件）；
+/// 文件不存在但目录存在时规范化目录部分；目录也不存在时只按当前目录补全，
+/// `..` 等保持原样，目录在第一次保存时创建
+fn canonical(path: &Path) -> io::Result<PathBuf> {
+    if let Ok(path) = fs::canonicalize(path) {
+        return Ok(path);
+    }
+    if let (Some(parent), Some(name)) = (path.parent(), path.file_name()) {
+        let parent = if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        };
+        if let Ok(parent) = fs::canonicalize(parent) {
+            return Ok(parent.join(name));
+        }
+    }
+    std::path::absolute(path)
 }
 
-impl Display for TodoItem {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Item: {} \nContent: {} \n(Priority: {})",
-            self.name, self.content, self.priority
+/// 打印提示并读取一行，只有输入 y 才返回 true
+fn confirm(prompt: &str) -> bool {
+    if YES.load(Ordering::Relaxed) {
+        return true;
+    }
+    // 安静模式多用于脚本，不能停下来等待输入
+    if QUIET.load(Ordering::Relaxed) {
+        eprintln!(
+            "{} Refusing without confirmation in --quiet mode (pass --yes to confirm).",
+            prompt
+        );
+        return false;
+    }
+    prompt_line(&format!("{}(y/N)", prompt))
+        .is_some_and(|answer| answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// 确认后才执行、否则整个命令取消的操作：安静模式下无从确认，报错退出而不是当作正常取消
+fn confirm_or_cancel(prompt: &str) -> Result<bool, Box<dyn Error>> {
+    if QUIET.load(Ordering::Relaxed) && !YES.load(Ordering::Relaxed) {
+        return Err(format!(
+            "{} Confirmation required in --quiet mode; pass --yes to confirm.",
+            prompt
         )
+        .into());
+    }
+    Ok(confirm(prompt))
+}
+
+/// 打印提示并读取一行（去掉行尾换行），输入结束（EOF）时返回 None
+fn prompt_line(prompt: &str) -> Option<String> {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    let mut buffer = String::new();
+    match io::stdin().read_line(&mut buffer) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(buffer.trim_end_matches(['\r', '\n']).to_string()),
     }
 }
 
-struct TodoList {
-    buffer: Vec<TodoItem>,
-    file: Mutex<fs::File>,
+/// 交互式读取要修改的字段，留空表示保持不变
+fn prompt_changes() -> ItemChanges {
+    let read = |prompt: &str| prompt_line(prompt).filter(|value| !value.trim().is_empty());
+    let name = read("New name: ").and_then(|value| match validate_name(&value) {
+        Ok(name) => Some(name),
+        Err(e) => {
+            println!("Keeping the current name: {}", e);
+            None
+        }
+    });
+    let content = read("New content: ");
+    let priority = read("New priority: ").and_then(|value| match parse_priority(value.trim()) {
+        Ok(priority) => Some(priority),
+        Err(e) => {
+            println!("Keeping the current priority: {}", e);
+            None
+        }
+    });
+    let url = read("New URL: ").and_then(|value| match parse_url(&value) {
+        Ok(url) => Some(url),
+        Err(e) => {
+            println!("Keeping the current URL: {}", e);
+            None
+        }
+    });
+    ItemChanges {
+        name,
+        content,
+        priority,
+        url,
+    }
+}
+
+/// 标记完成并报告结果；重复任务会提示下一次的截止日期
+fn complete_item(todo_list: &mut TodoList, item: &TodoItem) -> Result<bool, Box<dyn Error>> {
+    if !todo_list.mark_done(item.id)? {
+        return Ok(false);
+    }
+    info!("Marked {} as done.", item.name);
+    if let Some(next) = todo_list.pending_by_name(&item.name)
+        && let Some(due) = next.due
+    {
+        info!("Next occurrence is due {}.", due);
+    }
+    Ok(true)
 }
 
-impl TodoList {
-    fn add_item(&mut self, item: TodoItem) -> bool {
-        if self.buffer.iter().any(|i| calculate_hash(&i) == calculate_hash(&item)) {
-            return false;
+/// 复制到系统剪贴板；没有可用的剪贴板（如无图形界面的 SSH 会话）时改为直接打印
+fn copy_to_clipboard(text: &str) {
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        Ok(()) => info!("Copied to clipboard."),
+        Err(e) => {
+            eprintln!(
+                "Warning: clipboard is unavailable ({}), printing instead.",
+                e
+            );
+            println!("{}", text);
         }
-        self.buffer.push(item);
-        true
     }
+}
 
-    fn analysis(&self) -> &Vec<TodoItem> {
-        &self.buffer
+/// 用默认浏览器打开链接；打不开（如没有图形界面）时打印链接，由用户自行处理
+fn open_url(url: &str) {
+    match open::that_detached(url) {
+        Ok(()) => info!("Opened {}", url),
+        Err(e) => {
+            eprintln!(
+                "Warning: could not open a browser ({}), printing instead.",
+                e
+            );
+            println!("{}", url);
+        }
     }
+}
 
-    fn clear(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut file = self.file.lock().unwrap();
+/// 删除一项；它还有子任务时询问是否一并删除，否则把子任务提升为顶层任务
+fn delete_item(todo_list: &mut TodoList, item: &TodoItem) -> bool {
+    let descendants = todo_list.descendant_ids(item.id);
+    if !descendants.is_empty() {
+        if confirm(&format!(
+            "\"{}\" has {} subtask(s). Delete them too?",
+            item.name,
+            descendants.len()
+        )) {
+            for id in descendants {
+                todo_list.del_by_id(id);
+            }
+        } else {
+            todo_list.detach_children(item.id);
+        }
+    }
+    todo_list.del_by_id(item.id)
+}
 
-        // 步骤1：先刷新缓冲区，避免数据残留
-        file.flush()?;
+fn open_todo_list(path: String) -> Result<TodoList, Box<dyn Error>> {
+    let path = fix(path)?;
+    let todo_list = match TodoList::open(&path) {
+        Err(e) if offer_move_aside(e.as_ref()) => TodoList::open(&path),
+        result => result,
+    }?;
+    remember_path(&path);
+    Ok(todo_list)
+}
 
-        // 步骤2：截断文件为 0 字节（物理清空文件）
-        file.set_len(0)?;
+// 本次命令操作的待办文件（已经过 fix），命令成功后写入状态文件，供 `todo last` 使用；
+// 一个命令涉及多个文件时以最后记录的为准
+static LAST_PATH: Mutex<Option<String>> = Mutex::new(None);
 
-        // 步骤3：重置指针到开头，确保后续写入从正确位置开始
-        file.rewind()?;
+fn remember_path(path: &str) {
+    *LAST_PATH.lock().unwrap_or_else(|e| e.into_inner()) = Some(path.to_string());
+}
 
-        // 步骤4：同步清空内存中的 buffer（关键！否则 Drop 时会写回旧数据）
-        self.buffer.clear();
+/// 状态文件 `~/.local/state/todo/last`（遵循 XDG_STATE_HOME；没有 state 目录的平台放在数据目录下）
+fn last_path_file() -> Option<PathBuf> {
+    state_dir()
+        .or_else(data_dir)
+        .map(|dir| dir.join("todo").join("last"))
+}
 
-        Ok(())
+/// 记录失败不影响命令本身，静默忽略；查看不存在的文件（命令没有创建它）不记录
+fn save_last_path() {
+    let Some(path) = LAST_PATH.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    if !Path::new(&path).exists() {
+        return;
     }
-
-    fn del_by_name(&mut self, name: String) {
-        if let Some(index) = self.buffer.iter().position(|item| item.name == name) {
-            self.buffer.swap_remove(index);
+    if let Some(file) = last_path_file() {
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
         }
+        let _ = fs::write(file, path);
     }
+}
 
-    fn save_to_file(&self) -> Result<(), Box<dyn Error>> {
-        let serialized = serde_json::to_string(&self.buffer)?;
-        let mut file = self.file.lock().unwrap();
-        file.set_len(0)?; // 用 ? 替代 unwrap()
-        file.rewind()?;
-        file.write_all(serialized.as_bytes())?;
-        Ok(())
+fn load_last_path() -> Result<String, Box<dyn Error>> {
+    let file = last_path_file().ok_or("cannot determine the state directory.")?;
+    match fs::read_to_string(&file) {
+        Ok(path) if !path.trim().is_empty() => Ok(path.trim_end_matches('\n').to_string()),
+        Ok(_) => Err("No list has been used yet.".into()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err("No list has been used yet.".into()),
+        Err(e) => Err(format!("Failed to read {}: {}", file.display(), e).into()),
     }
+}
 
-    fn find_items_by_name(&self, keyword: &str) -> Vec<&TodoItem> {
-        let keyword_lower = keyword.to_lowercase();
-        self.buffer
-            .iter()
-            // 匹配规则：名称（小写）包含关键词（小写），覆盖更多场景
-            .filter(|item| item.name.to_lowercase().contains(&keyword_lower))
-            .collect()
-    }
-
-    fn open(value: &str) -> Result<Self, Box<dyn Error>> {
-        // 打开文件（只读、可写、不存在则创建）
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(value)
-            .map_err(|e| format!("无法打开文件: {}", e))?; // 更明确的错误提示
-
-        // 确保文件指针在开头
-        file.rewind()?;
-
-        // 读取文件内容（使用 ? 处理错误，而不是 unwrap）
-        let mut content = String::new();
-        file.read_to_string(&mut content)
-            .map_err(|e| format!("读取文件失败: {}", e))?;
-
-        // 解析 JSON（处理空内容或有效内容）
-        let buffer: Vec<TodoItem> = if content.trim().is_empty() {
-            Vec::new()
-        } else {
-            serde_json::from_str(&content)
-                .map_err(|e| format!("JSON 解析失败: {} (内容: {})", e, content))?
-        };
+/// 执行修改列表的命令，成功后保存；保存失败时整个命令以错误退出，而不是只在 Drop 时打印一行
+fn with_commit(
+    mut todo_list: TodoList,
+    command: impl FnOnce(&mut TodoList) -> Result<Outcome, Box<dyn Error>>,
+) -> Result<Outcome, Box<dyn Error>> {
+    let outcome = command(&mut todo_list)?;
+    todo_list.commit()?;
+    Ok(outcome)
+}
 
-        Ok(TodoList {
-            buffer,
-            file: Mutex::new(file),
-        })
+/// 只读打开：不加锁，文件或目录不存在时得到空列表而不创建；列表不关联文件，Drop 时不会写回
+fn open_todo_list_read_only(path: String) -> Result<TodoList, Box<dyn Error>> {
+    let path = fix(path)?;
+    let todo_list = match TodoList::snapshot(&path) {
+        Err(e) if offer_move_aside(e.as_ref()) => TodoList::snapshot(&path),
+        result => result,
+    }?;
+    remember_path(&path);
+    Ok(todo_list)
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<(), Box<dyn Error>> {
+    let json =
+        serde_json::to_string(value).map_err(|e| format!("Failed to serialize output: {}", e))?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// view 的分组方式
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum GroupBy {
+    Day, // 按截止日期分组，没有截止日期的放在最后
+}
+
+/// view/find --field 输出的字段
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum Field {
+    Name,
+    Content,
+    Priority,
+    Due,
+    Id,
+}
+
+impl Field {
+    /// 单行的值：没有截止日期时为空行，内容中的换行写成 \n，保证一项占一行
+    fn value(self, item: &TodoItem) -> String {
+        match self {
+            Field::Name => item.name.clone(),
+            Field::Content => item.content.replace('\n', "\\n"),
+            Field::Priority => item.priority.to_string(),
+            Field::Due => item.due.map(|due| due.to_string()).unwrap_or_default(),
+            Field::Id => item.id.to_string(),
+        }
     }
+}
 
-    fn open_without_doubt(value: &str) -> Self {
-        Self::open(value).unwrap_or_else(|e| {
-            println!("The formatting of file is invalid. \n {}", e);
-            exit(1);
-        })
+/// 每项只输出一个字段，一行一个值，便于在管道中使用；没有结果时什么都不输出
+fn print_field<'a>(items: impl IntoIterator<Item = &'a TodoItem>, field: Field) -> Outcome {
+    let mut found = false;
+    for item in items {
+        println!("{}", field.value(item));
+        found = true;
     }
+    Outcome::found(found)
 }
 
-impl Default for TodoList {
-    fn default() -> Self {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(get_default_path())
-            .unwrap();
-        file.set_len(0).unwrap();
-        file.rewind().unwrap();
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ExportFormat {
+    Markdown,
+    Csv,
+    Taskwarrior, // `task import` 可读取的 JSON
+}
 
-        TodoList {
-            buffer: Vec::new(),
-            file: Mutex::new(file),
-        }
+#[derive(ValueEnum, Debug, Clone, Copy)]
+enum ImportFormat {
+    Csv,
+    Github,      // GitHub issues API 返回的 JSON 数组
+    Taskwarrior, // `task export` 输出的 JSON 数组
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "Todo", version = version(), about, long_about = None)]
+struct Cli {
+    // 以 JSON 数组输出结果，不进入交互界面
+    #[arg(long, global = true)]
+    json: bool,
+    // 强制关闭彩色输出
+    #[arg(long, global = true)]
+    no_color: bool,
+    // 只在内存中执行并列出将发生的改动，不写入文件
+    #[arg(long, global = true)]
+    dry_run: bool,
+    // 不输出提示信息，只保留数据输出和错误
+    #[arg(short, long, global = true)]
+    quiet: bool,
+    // 所有确认提示自动回答 y，便于在脚本和定时任务中使用
+    #[arg(short, long, global = true)]
+    yes: bool,
+    // 使用配置文件 [profiles] 中该名称对应的待办文件；--path 仍然优先
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum TemplateAction {
+    // 新建模板，同名时覆盖
+    Add {
+        name: String,
+        content: Option<String>,
+        #[arg(short, long, value_parser = parse_priority, allow_negative_numbers = true)]
+        priority: Option<i16>,
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+    },
+    List,
+    Delete {
+        name: String,
+    },
+}
+
+// 待办文件路径：`--path` 和位置参数两种写法等价，都省略时使用默认路径（或 --profile 指定的路径）。
+// 只用于本身没有位置参数的命令，避免和名称、内容等参数混淆。
+// （这里不能用文档注释，clap 会把它当作子命令的说明显示在 --help 中）
+#[derive(Args, Debug)]
+struct PathArgs {
+    #[arg(long = "path", value_name = "PATH")]
+    flag: Option<String>,
+    #[arg(value_name = "PATH", conflicts_with = "flag")]
+    positional: Option<String>,
+}
+
+impl PathArgs {
+    fn resolve(self) -> String {
+        self.flag
+            .or(self.positional)
+            .unwrap_or_else(get_default_path)
     }
 }
 
-impl Drop for TodoList {
-    fn drop(&mut self) {
-        if let Err(e) = self.save_to_file() {
-            eprintln!("保存文件失败: {}", e);
+#[derive(Subcommand, Debug)]
+enum Command {
+    Add {
+        #[arg(short, long, default_value_t = String::from("Untitled"), value_parser = validate_name)]
+        name: String,
+        // 省略时依次取模板和配置中的默认优先级
+        #[arg(short, long, value_parser = parse_priority, allow_negative_numbers = true)]
+        priority: Option<i16>, // 优先级，-9 到 9 或 backlog/low/normal/high/critical，负数为 backlog
+        #[arg(long, value_parser = parse_date)]
+        due: Option<NaiveDate>, // 截止日期（YYYY-MM-DD）
+        #[arg(short, long = "tag")]
+        tags: Vec<String>, // 可重复指定多个标签
+        #[arg(long, value_parser = parse_recurrence)]
+        repeat: Option<Recurrence>, // 重复周期，如 daily、weekly、every:3d
+        #[arg(long)]
+        parent: Option<u64>, // 作为子任务挂在该 id 的条目下
+        #[arg(long, value_parser = parse_url)]
+        url: Option<String>, // 相关网页，须带协议，如 https://example.com
+        #[arg(long)]
+        path: Option<String>,
+        // 用配置中的模板预填内容、优先级和标签，命令行给出的值优先，标签取并集
+        #[arg(long)]
+        template: Option<String>,
+        // 从标准输入读取内容直到 EOF，适合多段落的长内容
+        #[arg(long, conflicts_with = "content")]
+        content_stdin: bool,
+        #[arg(required_unless_present_any = ["content_stdin", "template"])]
+        content: Option<String>,
+    },
+    // 从文本文件批量添加，每行一项：`名称` 或 `名称 :: 内容`，# 开头为注释
+    AddBulk {
+        #[command(flatten)]
+        path: PathArgs,
+        // `-` 表示从标准输入读取，便于脚本一次性传入大量条目（整个文件只读写一次）
+        #[arg(long)]
+        from: PathBuf,
+        #[arg(short, long, default_value_t = default_priority(), value_parser = parse_priority, allow_negative_numbers = true)]
+        priority: i16,
+    },
+    View {
+        #[command(flatten)]
+        path: PathArgs,
+        // 仅显示截止日期早于今天的待办项
+        #[arg(long)]
+        overdue: bool,
+        // 仅显示同时带有所有指定标签的待办项
+        #[arg(short, long = "tag")]
+        tags: Vec<String>,
+        #[arg(long, value_enum, default_value_t = default_sort())]
+        sort: SortField,
+        #[arg(long)]
+        reverse: bool,
+        // 只显示优先级在该范围内的项，任一端可省略
+        #[arg(long, value_parser = parse_priority, allow_negative_numbers = true)]
+        priority_min: Option<i16>,
+        #[arg(long, value_parser = parse_priority, allow_negative_numbers = true)]
+        priority_max: Option<i16>,
+        // 排序后只保留前 N 项，0 表示不限制
+        #[arg(long, default_value_t = 0)]
+        limit: usize,
+        // 同时显示 backlog（优先级为负数）的项，默认隐藏
+        #[arg(long)]
+        include_backlog: bool,
+        // 分组打印（不进入交互），如按截止日期逐日列出
+        #[arg(long, value_enum)]
+        group_by: Option<GroupBy>,
+        // 不进入交互，文件在磁盘上变化时重新打印列表，Ctrl-C 退出
+        #[arg(long)]
+        watch: bool,
+        // 不进入交互，每项只输出该字段的值，一行一个
+        #[arg(long, value_enum, conflicts_with_all = ["group_by", "watch"])]
+        field: Option<Field>,
+        // 只显示截止日期在该日期及之后的项（YYYY-MM-DD）
+        #[arg(long, value_parser = parse_date)]
+        after: Option<NaiveDate>,
+        // 只显示截止日期在该日期及之前的项
+        #[arg(long, value_parser = parse_date)]
+        before: Option<NaiveDate>,
+    },
+    // 排序后直接打印列表，从不进入交互，适合别名和脚本
+    List {
+        #[command(flatten)]
+        path: PathArgs,
+        #[arg(long, value_enum, default_value_t = default_sort())]
+        sort: SortField,
+        // 排序后只保留前 N 项，0 表示不限制
+        #[arg(long, default_value_t = 0)]
+        limit: usize,
+        // 同时显示 backlog（优先级为负数）的项，默认隐藏
+        #[arg(long)]
+        include_backlog: bool,
+    },
+    Find {
+        #[arg(long)]
+        path: Option<String>,
+        // 在内容中查找，而不是名称
+        #[arg(long, conflicts_with = "all_fields")]
+        content: bool,
+        // 同时在名称和内容中查找
+        #[arg(long)]
+        all_fields: bool,
+        // 把 name 当作正则表达式匹配名称
+        #[arg(long, conflicts_with_all = ["content", "all_fields"])]
+        regex: bool,
+        // 模糊匹配名称，结果按相似度从高到低排列
+        #[arg(long, conflicts_with_all = ["content", "all_fields", "regex"])]
+        fuzzy: bool,
+        // 名称区分大小写匹配
+        #[arg(long, conflicts_with_all = ["content", "all_fields", "regex", "fuzzy"])]
+        case_sensitive: bool,
+        // 只保留优先级最高的 N 项（--fuzzy 时为相似度最高），0 表示不限制
+        #[arg(long, default_value_t = 0)]
+        limit: usize,
+        // 每项只输出该字段的值，一行一个
+        #[arg(long, value_enum)]
+        field: Option<Field>,
+        // 只保留截止日期在该范围内（含两端）的项
+        #[arg(long, value_parser = parse_date)]
+        after: Option<NaiveDate>,
+        #[arg(long, value_parser = parse_date)]
+        before: Option<NaiveDate>,
+
+        name: String,
+    },
+    Clear {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    Delete {
+        #[arg(long)]
+        path: Option<String>,
+        // 按 id 精确删除，替代名称匹配
+        #[arg(long, conflicts_with = "name")]
+        id: Option<u64>,
+        // 只匹配名称完全相同（忽略大小写）的项，唯一匹配时直接删除
+        #[arg(long)]
+        exact: bool,
+        // 删除带有该标签的所有项，列出受影响的项后确认一次
+        #[arg(long, conflicts_with_all = ["name", "id", "priority"])]
+        tag: Option<String>,
+        // 删除该优先级的所有项，列出受影响的项后确认一次
+        #[arg(long, value_parser = parse_priority, allow_negative_numbers = true, conflicts_with_all = ["name", "id"])]
+        priority: Option<i16>,
+        // 删除名称包含 name 的所有项，不再逐个选择；列出受影响的项后确认一次
+        #[arg(long, requires = "name", conflicts_with = "exact")]
+        all: bool,
+
+        #[arg(required_unless_present_any = ["id", "tag", "priority"])]
+        name: Option<String>,
+    },
+    Done {
+        #[arg(long)]
+        path: Option<String>,
+
+        name: String,
+    },
+    // 从回收站（同目录下的 `<name>.trash.todo`）找回删除的项，多个匹配项时勾选
+    Restore {
+        #[arg(long)]
+        path: Option<String>,
+
+        name: String,
+    },
+    // 永久删除回收站中的所有项
+    EmptyTrash {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    Edit {
+        #[arg(long)]
+        path: Option<String>,
+        #[arg(long, value_parser = validate_name)]
+        new_name: Option<String>,
+        #[arg(short, long)]
+        content: Option<String>,
+        #[arg(short, long, value_parser = parse_priority, allow_negative_numbers = true)]
+        priority: Option<i16>,
+        #[arg(long, value_parser = parse_url)]
+        url: Option<String>,
+
+        name: String,
+    },
+    // 只改名称，不动内容和优先级；多个匹配项时在列表中选择
+    Rename {
+        #[arg(long)]
+        path: Option<String>,
+
+        old: String,
+        #[arg(value_parser = validate_name)]
+        new: String,
+    },
+    // 用默认浏览器打开匹配项的链接（add/edit --url 设置）
+    Open {
+        #[arg(long)]
+        path: Option<String>,
+
+        name: String,
+    },
+    // 把匹配项的内容复制到剪贴板
+    Copy {
+        #[arg(long)]
+        path: Option<String>,
+
+        name: String,
+    },
+    Export {
+        #[command(flatten)]
+        path: PathArgs,
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+        // 不指定时输出到标准输出，方便管道处理
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    Import {
+        #[command(flatten)]
+        path: PathArgs,
+        #[arg(short, long, value_enum, default_value_t = ImportFormat::Csv)]
+        format: ImportFormat,
+        // 要导入的源文件，可重复指定多个，各文件并行解析后按给出的顺序导入
+        #[arg(long, required = true)]
+        from: Vec<PathBuf>,
+        // 同时导入已关闭的 issue（仅 github 格式）
+        #[arg(long)]
+        include_closed: bool,
+    },
+    // 未完成项的数量，适合放在 shell 提示符中
+    Count {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    Stats {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    // 今天到期的和高优先级的未完成项，直接打印不进入交互
+    Today {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    Move {
+        #[arg(long)]
+        from: Option<String>,
+        #[arg(long)]
+        to: String,
+
+        name: String,
+    },
+    Merge {
+        #[arg(long)]
+        into: Option<String>,
+        #[arg(long)]
+        from: String,
+    },
+    Archive {
+        #[command(flatten)]
+        path: PathArgs,
+        // 默认为同目录下的 `<name>.archive.todo`
+        #[arg(long)]
+        archive_path: Option<String>,
+    },
+    // 合并名称（或名称加内容）重复的项
+    Dedup {
+        #[command(flatten)]
+        path: PathArgs,
+        #[arg(long, value_enum, default_value_t = DedupKey::Name)]
+        by: DedupKey,
+    },
+    // 尽量修复损坏的待办文件，丢弃无法解析的部分
+    Repair {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    // 用最近一次备份恢复文件
+    Undo {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    // 在交互列表中上下移动条目，保存为手动顺序（view --sort manual）
+    Reorder {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    // 对比两个待办文件（按名称对应），只读
+    Diff {
+        a: String,
+        b: String,
+    },
+    // 以 base 为共同祖先，把 remote 的改动合并进本地文件；两边都改过的项逐个选择
+    Sync {
+        remote: String,
+        #[arg(long)]
+        base: String,
+        #[arg(long)]
+        local: Option<String>,
+    },
+    // 打印命令实际会使用的待办文件（绝对路径），不打开也不修改文件
+    Where {
+        #[command(flatten)]
+        path: PathArgs,
+    },
+    // 打开最近一次成功的命令操作的待办文件（同 view）
+    Last {
+        // 同时显示 backlog（优先级为负数）的项，默认隐藏
+        #[arg(long)]
+        include_backlog: bool,
+    },
+    // 打印待办文件中条目的 JSON Schema
+    Schema,
+    // 列出配置文件中定义的 profile
+    Profiles,
+    // 管理配置文件中的模板（add --template 使用）
+    Template {
+        #[command(subcommand)]
+        action: TemplateAction,
+    },
+    // 生成补全脚本，如 `todo_cli completion zsh > _todo_cli`
+    #[command(hide = true)]
+    Completion {
+        #[arg(value_enum)]
+        shell: clap_complete::Shell,
+    },
+}
+
+/// 文件损坏时在终端询问是否把它移到 `.corrupt` 并从空列表重新开始；返回 true 表示已移走
+fn offer_move_aside(error: &(dyn Error + 'static)) -> bool {
+    let Some(corrupt) = error.downcast_ref::<CorruptFile>() else {
+        return false;
+    };
+    // --yes 只用于确认删除等常规操作，处理损坏文件仍需人工决定
+    if !io::stdin().is_terminal() || YES.load(Ordering::Relaxed) {
+        return false;
+    }
+    let aside = sibling_path(&corrupt.path, ".corrupt");
+    if !confirm(&format!(
+        "{} could not be parsed. Move it to {} and start with an empty list?",
+        corrupt.path.display(),
+        aside.display()
+    )) {
+        return false;
+    }
+    match fs::rename(&corrupt.path, &aside) {
+        Ok(()) => {
+            println!("Moved the damaged file to {}.", aside.display());
+            true
+        }
+        Err(e) => {
+            eprintln!("Failed to move {}: {}", corrupt.path.display(), e);
+            false
         }
     }
 }
@@ -265,16 +924,19 @@ impl<T> JoinHandlerScope<T> {
         self.handles.lock().unwrap().push(handle);
     }
 
-    fn join(&mut self) {
+    /// 等待所有线程结束，按加入的顺序返回各线程的结果
+    fn join(&mut self) -> Vec<T> {
+        let mut results = Vec::new();
         // 循环处理所有线程，包括等待过程中新增的线程
         while !self.handles.lock().unwrap().is_empty() {
             // 取出当前所有线程句柄
             let handles = self.handles.lock().unwrap().drain(..).collect::<Vec<_>>();
             // 逐个等待线程完成
             for handle in handles {
-                handle.join().unwrap();
+                results.push(handle.join().unwrap());
             }
         }
+        results
     }
 }
 
@@ -284,162 +946,2387 @@ impl<T> Drop for JoinHandlerScope<T> {
     }
 }
 
-fn main() {
-    let args = Command::parse();
-    match args {
-        Command::Add {
-            name,
-            content,
-            priority,
-            path,
-        } => {
-            let todo_item = TodoItem {
-                name,
-                content,
-                priority,
-            };
-            let mut todo_list = open_todo_list(path);
-            if !todo_list.add_item(todo_item) {
-                println!("There is another todo that is equal to this todo");
-                exit(0);
-            }
-        }
-        Command::View { path } => {
-            let todo_list = Arc::new(Mutex::new(open_todo_list(path)));
-            let todos = {
-                let list_clone = Arc::clone(&todo_list);
-                let mut todos = list_clone
-                    .lock()
-                    .unwrap()
-                    .analysis()
-                    .into_iter()
-                    .cloned()
-                    .collect::<Vec<TodoItem>>();
-                todos.sort_by(|a, b| b.priority.cmp(&a.priority));
-                todos
-            };
-            if todos.is_empty() {
-                println!("No item in history.");
-                return;
-            }
+/// 下拉菜单中的一项：菜单按 Display 逐行显示，这里只显示一行摘要并在行尾附上带颜色的优先级标记。
+/// 标记放在行尾，是因为其颜色的重置序列会结束菜单对选中行的反色高亮。
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DropDownEntry(TodoItem);
 
-            // 下拉菜单仅负责选择TodoItem，不处理后续操作
-            let mut selection_map = HashMap::new();
-            for todo in &todos {
-                // 存储待选的TodoItem（克隆一份，避免生命周期问题）
-                selection_map.insert(todo.clone(), Box::new(|_: &TodoItem| {}));
-            }
+// TodoItem 带有任意 JSON 的 extra 字段，无法派生 Hash；同一列表中 id 唯一，按 id 哈希即可
+impl Hash for DropDownEntry {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.id.hash(state);
+    }
+}
 
-            let dropdown = TerminalDropDown::use_drop_down(selection_map, todos.len() + 1);
-            // 等待用户选择一个TodoItem，此时下拉菜单占用输入流
-            let selected_todo = match dropdown.wait() {
-                Ok(Some(selected)) => selected, // 获取用户选择的TodoItem
-                Ok(None) => {
-                    println!("Canceled selection.");
-                    return;
-                }
-                Err(e) => {
-                    eprintln!("Error during selection: {:?}", e);
-                    return;
-                }
-            };
+impl Display for DropDownEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mark = if self.0.completed { "[x]" } else { "[ ]" };
+        write!(f, "{} {}", mark, badge_label(&self.0))
+    }
+}
 
-            // 下拉菜单已退出，输入流释放，此时处理用户操作选择
-            println!("What do you want?(1:Monopoly 2:Delete other: Cancel");
-            io::stdout().flush().unwrap();
-            let mut input = String::new();
-            io::stdin().read_line(&mut input).unwrap();
-            let selection = input.trim().parse::<usize>().unwrap_or_else(|_| {
-                println!("Canceled.");
-                exit(0);
-            });
+/// 选择列表中一项的文字：名称和 id，行尾附上带颜色的优先级标记（原因同 DropDownEntry）
+fn badge_label(item: &TodoItem) -> String {
+    format!(
+        "{} (ID: {}) {}",
+        item.name,
+        item.id,
+        priority_badge(item.priority)
+    )
+}
 
-            match selection {
-                1 => {
-                    // 查看操作：直接打印
-                    println!("--------------------\n{}\n--------------------", todos[selected_todo]);
-                }
-                2 => {
-                    // 删除操作：确认后执行
-                    println!("Are you sure?(y/N)");
-                    io::stdout().flush().unwrap();
-                    let mut confirm = String::new();
-                    io::stdin().read_line(&mut confirm).unwrap();
-                    if confirm.trim().to_lowercase() != "y" {
-                        println!("Canceled.");
-                        return;
-                    }
-                    // 执行删除
-                    let mut todo_list = todo_list.lock().unwrap();
-                    todo_list.del_by_name(todos[selected_todo].name().to_owned());
-                    println!("Done");
-                }
-                _ => {
-                    println!("Canceled.");
-                }
-            }
+fn priority_badge(priority: i16) -> String {
+    format!("[P{}]", priority)
+        .if_supports_color(Stream::Stdout, |badge| {
+            badge.style(priority_style(priority))
+        })
+        .to_string()
+}
+
+// 无法获取终端大小时（如被重定向）选择列表一屏显示的条目数
+const DEFAULT_VISIBLE_ROWS: usize = 20;
+// 选择列表中条目以外的行：标题、筛选条件、统计、操作提示及其间的空行
+const SELECT_CHROME_ROWS: usize = 7;
+
+/// 选择列表一屏最多显示的条目数：按终端高度扣除标题和提示行，至少一行，超出部分随光标滚动
+fn visible_rows() -> usize {
+    match crossterm::terminal::size() {
+        Ok((_, rows)) => usize::from(rows).saturating_sub(SELECT_CHROME_ROWS).max(1),
+        Err(_) => DEFAULT_VISIBLE_ROWS,
+    }
+}
+
+/// View 选中条目后可执行的操作，菜单按 ALL 的顺序编号
+#[derive(Debug, Clone, Copy)]
+enum ViewAction {
+    View,
+    Delete,
+    Edit,
+    MarkDone,
+    Copy,
+    Open,
+}
+
+impl ViewAction {
+    const ALL: [ViewAction; 6] = [
+        ViewAction::View,
+        ViewAction::Delete,
+        ViewAction::Edit,
+        ViewAction::MarkDone,
+        ViewAction::Copy,
+        ViewAction::Open,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            ViewAction::View => "View",
+            ViewAction::Delete => "Delete",
+            ViewAction::Edit => "Edit",
+            ViewAction::MarkDone => "Mark Done",
+            ViewAction::Copy => "Copy",
+            ViewAction::Open => "Open",
         }
-        Command::Find { path, name } => {
-            let todo_list = open_todo_list(path);
-            let found = todo_list.find_items_by_name(&name[..]);
-            if found.len() == 0 {
-                println!("No item with that name found");
-                return;
-            }
-            found.iter().for_each(|x| {
-                println!("--------------------\n{}\n--------------------", x);
-            })
+    }
+}
+
+/// 读取操作编号，输入无效时重新询问；输入 q 或输入结束时返回 None
+fn prompt_view_action() -> Option<ViewAction> {
+    let menu: Vec<String> = ViewAction::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, action)| format!("{}:{}", i + 1, action.label()))
+        .collect();
+    let prompt = format!("What do you want? ({} q:Cancel) ", menu.join(" "));
+    loop {
+        let input = prompt_line(&prompt)?;
+        let input = input.trim();
+        if input.eq_ignore_ascii_case("q") {
+            return None;
         }
-        Command::Clear { path } => {
-            exit_when_refuse();
-            let mut todo_list = open_todo_list(path);
-            todo_list.clear().unwrap_or_else(|e| {
-                eprintln!("There is something wrong. {}", e);
-                exit(1);
-            });
-            println!("Done.");
-        }
-        Command::Delete { path, name } => {
-            // 关键：TodoList 全程用 Arc<Mutex<>> 包装，确保 'static 生命周期
-            let todo_list = Arc::new(Mutex::new(open_todo_list(path)));
-            // 临时解锁读取匹配项，避免锁与 todo_list 生命周期绑定（解决 `list` 生命周期错误）
-            let todos: Vec<TodoItem> = {
-                let list_guard = todo_list.lock().unwrap(); // 临时锁
-                list_guard
-                    .find_items_by_name(&name[..])
-                    .into_iter()
-                    .cloned() // 克隆 TodoItem，脱离锁的生命周期
-                    .collect()
-            }; // 此处 list_guard 自动释放锁，避免生命周期问题
-
-            if todos.is_empty() {
-                println!("No item with that name found.");
-                return;
-            }
-
-            // 为每个待选项创建独立闭包（每个闭包克隆 Arc，满足 'static）
-            let mut drop_down_items = HashMap::new();
-            for todo in todos {
-                let list_clone = todo_list.clone(); // 克隆 Arc，每个闭包独立持有
-                drop_down_items.insert(todo.clone(), move |_selected: &TodoItem| {
-                    // 解锁执行删除（Arc 克隆确保生命周期足够）
-                    let mut list_guard = list_clone.lock().unwrap();
-                    list_guard.del_by_name(todo.name.clone());
-                    println!("\nSuccessfully deleted item: {}", todo.name);
-                });
-            }
-
-            // 启动下拉菜单并等待线程结束（确保生命周期匹配）
-            println!(
-                "Found {} matching items. Use Up/Down to select, Enter to delete, Esc to cancel.",
-                drop_down_items.len()
-            );
-            let dropdown = TerminalDropDown::use_drop_down(drop_down_items, 1);
-            if let Err(e) = dropdown.wait() {
-                eprintln!("Error during selection: {:?}", e);
-            }
+        if let Ok(choice) = input.parse::<usize>()
+            && let Some(action) = choice.checked_sub(1).and_then(|i| ViewAction::ALL.get(i))
+        {
+            return Some(*action);
+        }
+        println!("Please enter a number from 1 to {}.", ViewAction::ALL.len());
+    }
+}
 
-            println!("\nDelete command finished.");
+/// 多个候选项时让用户在多选列表中挑选，只有一项时直接返回；取消或出错时返回空列表
+fn pick_items(items: Vec<TodoItem>) -> Vec<TodoItem> {
+    if items.len() <= 1 {
+        return items;
+    }
+    let labels: Vec<String> = items.iter().map(badge_label).collect();
+    match multi_select(&labels, visible_rows()) {
+        Ok(Some(selected)) => selected.into_iter().map(|i| items[i].clone()).collect(),
+        Ok(None) => {
+            println!("Canceled selection.");
+            Vec::new()
+        }
+        Err(e) => {
+            eprintln!("Error during selection: {}", e);
+            Vec::new()
         }
     }
 }
+
+/// 终端多选列表：↑/↓ 移动，空格勾选，输入字符筛选，Enter 确认，Esc 取消。
+/// `labels` 为每一行显示的文字，返回被勾选项的下标；未勾选任何项时返回当前高亮项，取消时返回 None。
+fn multi_select(labels: &[String], max_show: usize) -> io::Result<Option<Vec<usize>>> {
+    if labels.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+    raw_select(labels, max_show, true)
+}
+
+/// 单选：Enter 选中当前项，Esc 取消返回 None
+fn single_select(labels: &[String], max_show: usize) -> io::Result<Option<usize>> {
+    if labels.is_empty() {
+        return Ok(None);
+    }
+    Ok(raw_select(labels, max_show, false)?.and_then(|selected| selected.first().copied()))
+}
+
+/// 终端支持时使用交互列表，否则（或列表运行出错时）退回到输入编号选择
+fn raw_select(
+    labels: &[String],
+    max_show: usize,
+    multiple: bool,
+) -> io::Result<Option<Vec<usize>>> {
+    if terminal_capable() {
+        let result =
+            RawModeGuard::enable().and_then(|_raw_mode| select_loop(labels, max_show, multiple));
+        if result.is_ok() {
+            return result;
+        }
+    }
+    Ok(numbered_select(labels, multiple))
+}
+
+/// 交互列表需要 stdin/stdout 都是终端、能取得终端大小，且不是 TERM=dumb 这类哑终端（Windows 控制台没有 TERM）
+fn terminal_capable() -> bool {
+    io::stdin().is_terminal()
+        && io::stdout().is_terminal()
+        && std::env::var_os("TERM").is_none_or(|term| term != "dumb")
+        && crossterm::terminal::size().is_ok_and(|(cols, rows)| cols > 0 && rows > 0)
+}
+
+/// 打印带编号的列表，读取一行编号（多选时可用空格或逗号分隔多个）；空行或输入结束时返回 None
+fn numbered_select(labels: &[String], multiple: bool) -> Option<Vec<usize>> {
+    for (i, label) in labels.iter().enumerate() {
+        println!("{:>3}) {}", i + 1, label);
+    }
+    let prompt = if multiple {
+        "Select items by number (e.g. 1 3), empty to cancel: "
+    } else {
+        "Select an item by number, empty to cancel: "
+    };
+    loop {
+        let input = prompt_line(prompt)?;
+        let numbers: Vec<&str> = input
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|part| !part.is_empty())
+            .collect();
+        if numbers.is_empty() {
+            return None;
+        }
+        let mut selected = Vec::new();
+        for number in &numbers {
+            match number.parse::<usize>() {
+                Ok(n) if (1..=labels.len()).contains(&n) => {
+                    if !selected.contains(&(n - 1)) {
+                        selected.push(n - 1);
+                    }
+                }
+                _ => {
+                    selected.clear();
+                    break;
+                }
+            }
+        }
+        if !selected.is_empty() && (multiple || selected.len() == 1) {
+            return Some(selected);
+        }
+        if multiple {
+            println!("Please enter numbers from 1 to {}.", labels.len());
+        } else {
+            println!("Please enter one number from 1 to {}.", labels.len());
+        }
+    }
+}
+
+/// 持有期间终端处于 raw 模式；离开作用域时（包括出错和 panic）恢复终端状态
+struct RawModeGuard;
+
+impl RawModeGuard {
+    fn enable() -> io::Result<Self> {
+        enable_raw_mode()?;
+        Ok(RawModeGuard)
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        let _ = disable_raw_mode();
+    }
+}
+
+// multiple 为 false 时不显示勾选框，空格和其他字符一样用于筛选。
+// 输入字符即按行文字（忽略大小写）筛选可见条目，Backspace 删除；筛选条件不为空时 Esc 先清空条件。
+// 勾选状态与筛选无关，Enter 返回所有勾选项，包括当前被筛掉的
+fn select_loop(
+    labels: &[String],
+    max_show: usize,
+    multiple: bool,
+) -> io::Result<Option<Vec<usize>>> {
+    // 按看到的文字筛选，不匹配颜色控制序列
+    let lowered: Vec<String> = labels
+        .iter()
+        .map(|label| strip_ansi(label).to_lowercase())
+        .collect();
+    let mut query = String::new();
+    let mut visible: Vec<usize> = (0..labels.len()).collect();
+    let mut current_idx = 0; // visible 中的位置
+    let mut chosen = vec![false; labels.len()];
+    loop {
+        render_select(
+            labels,
+            &visible,
+            multiple.then_some(&chosen[..]),
+            current_idx,
+            max_show,
+            &query,
+        )?;
+        let Event::Key(key_event) = event::read()? else {
+            continue; // 忽略非键盘事件
+        };
+        if key_event.kind != KeyEventKind::Press {
+            continue;
+        }
+        let count = visible.len();
+        let last = count.saturating_sub(1);
+        let mut query_changed = false;
+        match key_event.code {
+            KeyCode::Up => {
+                current_idx = if current_idx == 0 {
+                    last
+                } else {
+                    current_idx - 1
+                }
+            }
+            KeyCode::Down => {
+                current_idx = if current_idx >= last {
+                    0
+                } else {
+                    current_idx + 1
+                }
+            }
+            KeyCode::PageUp => current_idx = current_idx.saturating_sub(max_show),
+            KeyCode::PageDown => current_idx = (current_idx + max_show).min(last),
+            KeyCode::Home => current_idx = 0,
+            KeyCode::End => current_idx = last,
+            KeyCode::Char(' ') if multiple => {
+                if let Some(&i) = visible.get(current_idx) {
+                    chosen[i] = !chosen[i];
+                }
+            }
+            KeyCode::Enter => {
+                let mut selected: Vec<usize> = (0..labels.len()).filter(|&i| chosen[i]).collect();
+                if selected.is_empty() {
+                    // 没有勾选且筛选结果为空时不做任何事
+                    let Some(&i) = visible.get(current_idx) else {
+                        continue;
+                    };
+                    selected.push(i);
+                }
+                return Ok(Some(selected));
+            }
+            KeyCode::Esc if !query.is_empty() => {
+                query.clear();
+                query_changed = true;
+            }
+            KeyCode::Esc => return Ok(None),
+            // raw 模式下 Ctrl-C 不会产生信号，当作取消处理
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            KeyCode::Backspace => query_changed = query.pop().is_some(),
+            KeyCode::Char(c) if !key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                query.push(c);
+                query_changed = true;
+            }
+            _ => {}
+        }
+        if query_changed {
+            let needle = query.to_lowercase();
+            visible = (0..labels.len())
+                .filter(|&i| lowered[i].contains(&needle))
+                .collect();
+            current_idx = 0;
+        }
+    }
+}
+
+/// 去掉 ESC [ … 字母 形式的控制序列（颜色、样式）
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            chars.by_ref().find(|c| c.is_ascii_alphabetic());
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn render_select(
+    labels: &[String],
+    visible: &[usize],
+    chosen: Option<&[bool]>,
+    current_idx: usize,
+    max_show: usize,
+    query: &str,
+) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.execute(Clear(ClearType::All))?;
+    stdout.execute(crossterm::cursor::MoveTo(0, 0))?;
+
+    let (start_idx, end_idx) = visible_window(visible.len(), current_idx, max_show);
+
+    // raw 模式下换行不会回到行首，需要显式输出 \r\n
+    write!(stdout, "Please select.（ESC for canceling）:\r\n")?;
+    write!(stdout, "Filter: {}\r\n", query)?;
+    write!(stdout, "Total: {}", labels.len())?;
+    if !query.is_empty() {
+        write!(stdout, " | Matching: {}", visible.len())?;
+    }
+    if !visible.is_empty() {
+        write!(stdout, " | Showing: {} - {}", start_idx + 1, end_idx)?;
+    }
+    if let Some(chosen) = chosen {
+        write!(
+            stdout,
+            " | Selected: {}",
+            chosen.iter().filter(|&&c| c).count()
+        )?;
+    }
+    write!(stdout, "\r\n\r\n")?;
+    if visible.is_empty() {
+        write!(stdout, "  (no matches)\r\n")?;
+    }
+    for (pos, &i) in visible.iter().enumerate().take(end_idx).skip(start_idx) {
+        let line = match chosen {
+            Some(chosen) => format!("{} {}", if chosen[i] { "[*]" } else { "[ ]" }, labels[i]),
+            None => labels[i].clone(),
+        };
+        if pos == current_idx {
+            write!(stdout, "\x1B[7m> {}\x1B[0m\r\n", line)?;
+        } else {
+            write!(stdout, "  {}\r\n", line)?;
+        }
+    }
+    let toggle = if chosen.is_some() {
+        " | Space: Toggle"
+    } else {
+        ""
+    };
+    write!(
+        stdout,
+        "\r\n↑/↓: Move | PgUp/PgDn: Page{} | Type: Filter | Enter: Confirm | ESC: Cancel\r\n",
+        toggle
+    )?;
+    stdout.flush()
+}
+
+/// 条目多于可显示行数时，窗口跟随当前项滑动；返回显示范围 [start, end)
+fn visible_window(total: usize, current_idx: usize, max_show: usize) -> (usize, usize) {
+    let max_show = max_show.max(1);
+    let start_idx = if total <= max_show {
+        0
+    } else {
+        current_idx
+            .saturating_sub(max_show / 2)
+            .min(total - max_show)
+    };
+    (start_idx, (start_idx + max_show).min(total))
+}
+
+/// 交互调整顺序：Space 拿起/放下当前项，拿起时 ↑/↓ 带着它移动。
+/// Enter 确认后按新的顺序返回原下标，Esc 或 Ctrl-C 取消返回 None
+fn reorder_items(labels: &[String], max_show: usize) -> io::Result<Option<Vec<usize>>> {
+    if labels.is_empty() {
+        return Ok(Some(Vec::new()));
+    }
+    let _raw_mode = RawModeGuard::enable()?;
+    let mut order: Vec<usize> = (0..labels.len()).collect();
+    let mut current_idx = 0;
+    let mut grabbed = false;
+    loop {
+        render_reorder(labels, &order, current_idx, grabbed, max_show)?;
+        let Event::Key(key_event) = event::read()? else {
+            continue;
+        };
+        if key_event.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key_event.code {
+            KeyCode::Up if current_idx > 0 => {
+                if grabbed {
+                    order.swap(current_idx, current_idx - 1);
+                }
+                current_idx -= 1;
+            }
+            KeyCode::Down if current_idx + 1 < order.len() => {
+                if grabbed {
+                    order.swap(current_idx, current_idx + 1);
+                }
+                current_idx += 1;
+            }
+            KeyCode::Char(' ') => grabbed = !grabbed,
+            KeyCode::Enter => return Ok(Some(order)),
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(None);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn render_reorder(
+    labels: &[String],
+    order: &[usize],
+    current_idx: usize,
+    grabbed: bool,
+    max_show: usize,
+) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.execute(Clear(ClearType::All))?;
+    stdout.execute(crossterm::cursor::MoveTo(0, 0))?;
+
+    let (start_idx, end_idx) = visible_window(order.len(), current_idx, max_show);
+    write!(stdout, "Reorder items.（ESC for canceling）:\r\n")?;
+    write!(
+        stdout,
+        "Total: {} | Showing: {} - {}\r\n\r\n",
+        order.len(),
+        start_idx + 1,
+        end_idx
+    )?;
+    for (i, &index) in order.iter().enumerate().take(end_idx).skip(start_idx) {
+        let line = format!("{:>3}. {}", i + 1, labels[index]);
+        if i != current_idx {
+            write!(stdout, "  {}\r\n", line)?;
+        } else if grabbed {
+            write!(stdout, "\x1B[7m= {}\x1B[0m\r\n", line)?;
+        } else {
+            write!(stdout, "\x1B[7m> {}\x1B[0m\r\n", line)?;
+        }
+    }
+    write!(
+        stdout,
+        "\r\n↑/↓: Move | Space: Pick up / put down | Enter: Save | ESC: Cancel\r\n"
+    )?;
+    stdout.flush()
+}
+
+/// 命令的执行结果，决定进程的退出码：成功为 0，没有匹配项为 2，出错为 1
+enum Outcome {
+    Done,
+    NotFound,
+}
+
+impl Outcome {
+    fn found(found: bool) -> Self {
+        if found {
+            Outcome::Done
+        } else {
+            Outcome::NotFound
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        match outcome {
+            Outcome::Done => ExitCode::SUCCESS,
+            Outcome::NotFound => ExitCode::from(2),
+        }
+    }
+}
+
+// run 返回时所有 TodoList 都已正常 Drop（写回文件），再据结果设置退出码
+fn main() -> ExitCode {
+    // 在提示输入等非 raw 模式下按 Ctrl-C：等正在进行的保存结束、恢复终端后再退出
+    let _ = ctrlc::set_handler(|| {
+        let _saving = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let _ = disable_raw_mode();
+        println!();
+        std::process::exit(130);
+    });
+    // clap 默认以 2 退出参数错误，与“没有匹配项”冲突；这里统一为 1，--help/--version 仍为 0
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+        }
+    };
+    match run(cli) {
+        Ok(outcome) => {
+            save_last_path();
+            outcome.into()
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// view 的筛选和排序选项
+struct ViewOptions {
+    overdue: bool,
+    tags: Vec<String>,
+    sort: SortField,
+    reverse: bool,
+    priority_min: Option<i16>,
+    priority_max: Option<i16>,
+    limit: usize,
+    include_backlog: bool,
+    group_by: Option<GroupBy>,
+    field: Option<Field>,
+    due: DueRange,
+}
+
+/// --after/--before 指定的截止日期范围（含两端）；设置了任一端时，没有截止日期的项不在范围内
+#[derive(Debug, Clone, Copy, Default)]
+struct DueRange {
+    after: Option<NaiveDate>,
+    before: Option<NaiveDate>,
+}
+
+impl DueRange {
+    fn new(after: Option<NaiveDate>, before: Option<NaiveDate>) -> Result<Self, String> {
+        if let (Some(after), Some(before)) = (after, before)
+            && after > before
+        {
+            return Err(format!(
+                "--after {} is later than --before {}.",
+                after, before
+            ));
+        }
+        Ok(DueRange { after, before })
+    }
+
+    fn contains(&self, item: &TodoItem) -> bool {
+        if self.after.is_none() && self.before.is_none() {
+            return true;
+        }
+        item.due.is_some_and(|due| {
+            self.after.is_none_or(|after| due >= after)
+                && self.before.is_none_or(|before| due <= before)
+        })
+    }
+}
+
+/// delete 的批量删除条件
+enum BatchDelete {
+    Tag(String),
+    Priority(i16),
+    Name(String), // 名称（忽略大小写）包含该关键词，与 find 的默认匹配相同；已转为小写
+}
+
+impl BatchDelete {
+    fn matches(&self, item: &TodoItem) -> bool {
+        match self {
+            BatchDelete::Tag(tag) => item.tags.contains(tag),
+            BatchDelete::Priority(priority) => item.priority == *priority,
+            BatchDelete::Name(keyword) => item.name.to_lowercase().contains(keyword),
+        }
+    }
+}
+
+impl Display for BatchDelete {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchDelete::Tag(tag) => write!(f, "tag {}", tag),
+            BatchDelete::Priority(priority) => write!(f, "priority {}", priority),
+            BatchDelete::Name(keyword) => write!(f, "a name containing {:?}", keyword),
+        }
+    }
+}
+
+/// find 的匹配方式，对应互斥的命令行开关
+#[derive(Clone, Copy, PartialEq)]
+enum FindMode {
+    Name,
+    NameCased,
+    Content,
+    AllFields,
+    Regex,
+    Fuzzy,
+}
+
+// 各命令的实现放在 cmd_* 函数中，这里只负责参数转换和打开文件
+fn run(cli: Cli) -> Result<Outcome, Box<dyn Error>> {
+    let json = cli.json;
+    DRY_RUN.store(cli.dry_run, Ordering::Relaxed);
+    QUIET.store(cli.quiet, Ordering::Relaxed);
+    YES.store(cli.yes, Ordering::Relaxed);
+    BACKUPS.store(
+        config().backups.unwrap_or(DEFAULT_BACKUPS),
+        Ordering::Relaxed,
+    );
+    PRETTY_JSON.store(config().pretty.unwrap_or(false), Ordering::Relaxed);
+    if let Some(profile) = &cli.profile {
+        let _ = PROFILE_PATH.set(profile_path(profile)?);
+    }
+    if cli.no_color {
+        owo_colors::set_override(false);
+    }
+    match cli.command {
+        Command::Add {
+            name,
+            content,
+            content_stdin,
+            priority,
+            due,
+            tags,
+            repeat,
+            parent,
+            url,
+            path,
+            template,
+        } => {
+            let template = match template {
+                Some(name) => find_template(&name)?,
+                None => Template::default(),
+            };
+            let content = if content_stdin {
+                read_stdin_content()?
+            } else {
+                content.unwrap_or(template.content)
+            };
+            let priority = priority
+                .or(template.priority)
+                .unwrap_or_else(default_priority);
+            let todo_item = TodoItem {
+                due,
+                tags: normalize_tags(template.tags.into_iter().chain(tags).collect()),
+                recurrence: repeat,
+                parent,
+                url,
+                ..TodoItem::new(name, content, priority)
+            };
+            cmd_add(fix(path.unwrap_or_else(get_default_path))?, todo_item)
+        }
+        Command::AddBulk {
+            path,
+            from,
+            priority,
+        } => cmd_add_bulk(path.resolve(), &from, priority),
+        Command::View {
+            path,
+            overdue,
+            tags,
+            sort,
+            reverse,
+            priority_min,
+            priority_max,
+            limit,
+            include_backlog,
+            group_by,
+            watch,
+            field,
+            after,
+            before,
+        } => {
+            let options = ViewOptions {
+                overdue,
+                tags: normalize_tags(tags),
+                sort,
+                reverse,
+                priority_min,
+                priority_max,
+                limit,
+                include_backlog,
+                group_by,
+                field,
+                due: DueRange::new(after, before)?,
+            };
+            if watch {
+                return cmd_watch(fix(path.resolve())?, &options, json);
+            }
+            let todo_list = open_todo_list(path.resolve())?;
+            with_commit(todo_list, |todo_list| cmd_view(todo_list, &options, json))
+        }
+        Command::List {
+            path,
+            sort,
+            limit,
+            include_backlog,
+        } => {
+            let options = ViewOptions {
+                overdue: false,
+                tags: Vec::new(),
+                sort,
+                reverse: false,
+                priority_min: None,
+                priority_max: None,
+                limit,
+                include_backlog,
+                group_by: None,
+                field: None,
+                due: DueRange::default(),
+            };
+            let todo_list = open_todo_list_read_only(path.resolve())?;
+            cmd_list(&todo_list, &options, json)
+        }
+        Command::Find {
+            path,
+            content,
+            all_fields,
+            regex,
+            fuzzy,
+            case_sensitive,
+            limit,
+            field,
+            after,
+            before,
+            name,
+        } => {
+            // 这些开关在 clap 中已声明为互斥
+            let mode = if fuzzy {
+                FindMode::Fuzzy
+            } else if regex {
+                FindMode::Regex
+            } else if all_fields {
+                FindMode::AllFields
+            } else if content {
+                FindMode::Content
+            } else if case_sensitive {
+                FindMode::NameCased
+            } else {
+                FindMode::Name
+            };
+            let due = DueRange::new(after, before)?;
+            let path = fix(path.unwrap_or_else(get_default_path))?;
+            // 默认的名称查找在大文件上走索引，只解析命中的行
+            let indexed = if mode == FindMode::Name {
+                find_by_name_indexed(Path::new(&path), &name)
+            } else {
+                None
+            };
+            let found = match indexed {
+                Some(found) => {
+                    remember_path(&path);
+                    found
+                }
+                None => find_matches(&open_todo_list_read_only(path)?, &name, mode)?,
+            };
+            cmd_find(found, mode, due, limit, field, json)
+        }
+        Command::Clear { path } => cmd_clear(path.resolve()),
+        Command::Delete {
+            path,
+            id,
+            exact,
+            tag,
+            priority,
+            all,
+            name,
+        } => {
+            let batch = match (tag, priority) {
+                (Some(tag), _) => {
+                    let tag = normalize_tags(vec![tag]).pop();
+                    Some(BatchDelete::Tag(tag.ok_or("tag must not be empty.")?))
+                }
+                (None, Some(priority)) => Some(BatchDelete::Priority(priority)),
+                // 空关键词会匹配所有项，交给 clear 处理
+                (None, None) if all => match name.as_deref().map(str::trim) {
+                    Some(keyword) if !keyword.is_empty() => {
+                        Some(BatchDelete::Name(keyword.to_lowercase()))
+                    }
+                    _ => return Err("name must not be empty with --all (use `clear`).".into()),
+                },
+                (None, None) => None,
+            };
+            let todo_list = open_todo_list(path.unwrap_or_else(get_default_path))?;
+            with_commit(todo_list, |todo_list| match (id, batch) {
+                (Some(id), _) => cmd_delete_by_id(todo_list, id),
+                (None, Some(batch)) => cmd_delete_batch(todo_list, batch),
+                (None, None) => cmd_delete(todo_list, &name.unwrap_or_default(), exact),
+            })
+        }
+        Command::Restore { path, name } => {
+            cmd_restore(fix(path.unwrap_or_else(get_default_path))?, &name)
+        }
+        Command::EmptyTrash { path } => cmd_empty_trash(fix(path.resolve())?),
+        Command::Done { path, name } => {
+            let todo_list = open_todo_list(path.unwrap_or_else(get_default_path))?;
+            with_commit(todo_list, |todo_list| cmd_done(todo_list, &name))
+        }
+        Command::Edit {
+            path,
+            new_name,
+            content,
+            priority,
+            url,
+            name,
+        } => {
+            let changes = ItemChanges {
+                name: new_name,
+                content,
+                priority,
+                url,
+            };
+            cmd_edit(path.unwrap_or_else(get_default_path), name, changes)
+        }
+        Command::Rename { path, old, new } => {
+            let todo_list = open_todo_list(path.unwrap_or_else(get_default_path))?;
+            with_commit(todo_list, |todo_list| cmd_rename(todo_list, &old, &new))
+        }
+        Command::Open { path, name } => {
+            let todo_list = open_todo_list_read_only(path.unwrap_or_else(get_default_path))?;
+            cmd_open(&todo_list, &name)
+        }
+        Command::Copy { path, name } => {
+            let todo_list = open_todo_list_read_only(path.unwrap_or_else(get_default_path))?;
+            cmd_copy(&todo_list, &name)
+        }
+        Command::Export {
+            path,
+            format,
+            output,
+        } => {
+            let todo_list = open_todo_list_read_only(path.resolve())?;
+            cmd_export(&todo_list, format, output.as_deref())
+        }
+        Command::Import {
+            path,
+            format,
+            from,
+            include_closed,
+        } => cmd_import(path.resolve(), format, from, include_closed),
+        Command::Count { path } => cmd_count(fix(path.resolve())?, json),
+        Command::Stats { path } => {
+            let todo_list = open_todo_list_read_only(path.resolve())?;
+            cmd_stats(&todo_list, json)
+        }
+        Command::Today { path } => {
+            let todo_list = open_todo_list_read_only(path.resolve())?;
+            cmd_today(&todo_list, json)
+        }
+        Command::Move { from, to, name } => {
+            cmd_move(fix(from.unwrap_or_else(get_default_path))?, fix(to)?, &name)
+        }
+        Command::Merge { into, from } => {
+            cmd_merge(fix(into.unwrap_or_else(get_default_path))?, fix(from)?)
+        }
+        Command::Archive { path, archive_path } => {
+            let path = fix(path.resolve())?;
+            let archive_path = match archive_path {
+                Some(archive_path) => fix(archive_path)?,
+                None => Path::new(&path)
+                    .with_extension("archive.todo")
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            cmd_archive(path, archive_path)
+        }
+        Command::Dedup { path, by } => {
+            let todo_list = open_todo_list(path.resolve())?;
+            with_commit(todo_list, |todo_list| cmd_dedup(todo_list, by))
+        }
+        Command::Repair { path } => cmd_repair(fix(path.resolve())?),
+        Command::Undo { path } => cmd_undo(fix(path.resolve())?),
+        Command::Reorder { path } => {
+            let todo_list = open_todo_list(path.resolve())?;
+            with_commit(todo_list, cmd_reorder)
+        }
+        Command::Diff { a, b } => cmd_diff(fix(a)?, fix(b)?, json),
+        Command::Sync {
+            remote,
+            base,
+            local,
+        } => {
+            let (remote, base) = (fix(remote)?, fix(base)?);
+            let todo_list = open_todo_list(local.unwrap_or_else(get_default_path))?;
+            with_commit(todo_list, |todo_list| cmd_sync(todo_list, remote, base))
+        }
+        Command::Where { path } => {
+            let (path, source) = match path.flag.or(path.positional) {
+                Some(path) => (path, "--path"),
+                None => default_path_with_source(),
+            };
+            cmd_where(fix(path)?, source, json)
+        }
+        Command::Last { include_backlog } => {
+            let options = ViewOptions {
+                overdue: false,
+                tags: Vec::new(),
+                sort: default_sort(),
+                reverse: false,
+                priority_min: None,
+                priority_max: None,
+                limit: 0,
+                include_backlog,
+                group_by: None,
+                field: None,
+                due: DueRange::default(),
+            };
+            let path = load_last_path()?;
+            if !json {
+                info!("{}", path);
+            }
+            let todo_list = open_todo_list(path)?;
+            with_commit(todo_list, |todo_list| cmd_view(todo_list, &options, json))
+        }
+        Command::Schema => {
+            println!("{}", serde_json::to_string_pretty(&item_schema())?);
+            Ok(Outcome::Done)
+        }
+        Command::Profiles => cmd_profiles(json),
+        Command::Template { action } => cmd_template(action, json),
+        Command::Completion { shell } => {
+            clap_complete::generate(
+                shell,
+                &mut Cli::command(),
+                env!("CARGO_BIN_NAME"),
+                &mut io::stdout(),
+            );
+            Ok(Outcome::Done)
+        }
+    }
+}
+
+/// 从标准输入读取内容直到 EOF，去掉末尾的换行
+fn read_stdin_content() -> Result<String, Box<dyn Error>> {
+    let mut buffer = String::new();
+    io::stdin()
+        .read_to_string(&mut buffer)
+        .map_err(|e| format!("Failed to read content from stdin: {}", e))?;
+    Ok(buffer.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn cmd_add(path: String, todo_item: TodoItem) -> Result<Outcome, Box<dyn Error>> {
+    let (name, priority) = (todo_item.name.clone(), todo_item.priority);
+    let outcome = match TodoList::append_to_file(&path, todo_item.clone()) {
+        Err(e) if offer_move_aside(e.as_ref()) => TodoList::append_to_file(&path, todo_item),
+        result => result,
+    };
+    remember_path(&path);
+    let added = match outcome? {
+        AppendOutcome::Added(added) => added,
+        AppendOutcome::Duplicate(conflict) => {
+            info!(
+                "There is another todo with the same name:\n--------------------\n{}\n--------------------",
+                conflict
+            );
+            return Ok(Outcome::Done);
+        }
+        AppendOutcome::Rejected(e) => return Err(format!("Cannot add {:?}: {}", name, e).into()),
+        AppendOutcome::MissingParent(parent) => {
+            return Err(format!("No item with id {} to use as the parent.", parent).into());
+        }
+    };
+    // 试运行时 append_to_file 已经打印了预览，并没有真的添加
+    if DRY_RUN.load(Ordering::Relaxed) {
+        return Ok(Outcome::Done);
+    }
+    info!(
+        "Added \"{}\" (ID: {}, priority {}) to {}",
+        name, added.id, priority, path
+    );
+    // 提示容易误加的情况，方便及时发现
+    if name == "Untitled" {
+        info!("Note: no --name was given, so the item is named \"Untitled\".");
+    }
+    if is_backlog(priority) {
+        info!("Note: the item is in the backlog; `view` shows it only with --include-backlog.");
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_add_bulk(path: String, from: &Path, priority: i16) -> Result<Outcome, Box<dyn Error>> {
+    let text = if from == Path::new("-") {
+        read_stdin_content()?
+    } else {
+        fs::read_to_string(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?
+    };
+    let mut todo_list = open_todo_list(path)?;
+    let (mut added, mut skipped, mut invalid) = (0, 0, 0);
+    for item in parse_bulk_lines(&text, priority) {
+        match todo_list.add_item(item) {
+            Ok(()) => added += 1,
+            Err(AddError::Duplicate(_)) => skipped += 1,
+            Err(e) => {
+                info!("Skipped a line: {}", e);
+                invalid += 1;
+            }
+        }
+    }
+    if invalid > 0 {
+        info!(
+            "Added {} items, skipped {} duplicates and {} invalid lines.",
+            added, skipped, invalid
+        );
+    } else {
+        info!("Added {} items, skipped {} duplicates.", added, skipped);
+    }
+    todo_list.commit()?;
+    Ok(Outcome::Done)
+}
+
+fn cmd_view(
+    todo_list: &mut TodoList,
+    options: &ViewOptions,
+    json: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let todos = filter_items(todo_list.analysis(), options);
+    if json {
+        print_json(&todos)?;
+        return Ok(Outcome::found(!todos.is_empty()));
+    }
+    if let Some(field) = options.field {
+        return Ok(print_field(&todos, field));
+    }
+    if todos.is_empty() {
+        if options.overdue {
+            info!("No overdue items.");
+        } else {
+            info!("No item in history.");
+        }
+        return Ok(Outcome::NotFound);
+    }
+    if let Some(GroupBy::Day) = options.group_by {
+        print_grouped_by_day(&todos);
+        return Ok(Outcome::Done);
+    }
+
+    // 管道或定时任务中没有终端可交互，直接逐行输出
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        let (todos, labels) = tree_labels(&todos, false);
+        print_plain_list(&todos, &labels);
+        return Ok(Outcome::Done);
+    }
+    let (todos, labels) = tree_labels(&todos, true);
+
+    // 多选列表仅负责选择TodoItem，不处理后续操作
+    let selected_todos: Vec<&TodoItem> = match multi_select(&labels, visible_rows()) {
+        Ok(Some(selected)) => selected.into_iter().map(|i| &todos[i]).collect(),
+        Ok(None) => {
+            println!("Canceled selection.");
+            return Ok(Outcome::Done);
+        }
+        Err(e) => return Err(format!("Error during selection: {}", e).into()),
+    };
+
+    // 选择列表已退出，输入流释放，此时处理用户操作选择
+    let Some(action) = prompt_view_action() else {
+        println!("Canceled.");
+        return Ok(Outcome::Done);
+    };
+    match action {
+        ViewAction::View => {
+            for todo in &selected_todos {
+                println!("--------------------\n{}\n--------------------", todo);
+            }
+        }
+        ViewAction::Delete => {
+            // 先完整显示将被删除的项，确认一次后删除所有选中项
+            for todo in &selected_todos {
+                println!("--------------------\n{}\n--------------------", todo);
+            }
+            if !confirm_or_cancel(&format!(
+                "Delete {} items. Are you sure?",
+                selected_todos.len()
+            ))? {
+                println!("Canceled.");
+                return Ok(Outcome::Done);
+            }
+            for todo in &selected_todos {
+                if delete_item(todo_list, todo) {
+                    println!("Deleted {}", todo.name);
+                } else {
+                    println!("No item named {}", todo.name);
+                }
+            }
+        }
+        ViewAction::Edit => {
+            for todo in &selected_todos {
+                println!(
+                    "Editing {} (leave blank to keep the current value)",
+                    todo.name
+                );
+                let changes = prompt_changes();
+                if changes.is_empty() {
+                    println!("Nothing to change.");
+                    continue;
+                }
+                // 一项出错不影响其他选中项
+                match edit_selected(todo_list, todo, changes) {
+                    Ok(true) => println!("Updated item: {}", todo.name),
+                    Ok(false) => {}
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            }
+        }
+        ViewAction::MarkDone => {
+            for todo in &selected_todos {
+                // 一项出错不影响其他选中项
+                match complete_item(todo_list, todo) {
+                    Ok(true) => {}
+                    Ok(false) => println!("{} is already done.", todo.name),
+                    Err(e) => eprintln!("Error: {}", e),
+                }
+            }
+        }
+        ViewAction::Copy => {
+            let contents: Vec<&str> = selected_todos
+                .iter()
+                .map(|todo| todo.content.as_str())
+                .collect();
+            copy_to_clipboard(&contents.join("\n\n"));
+        }
+        ViewAction::Open => {
+            for todo in &selected_todos {
+                match &todo.url {
+                    Some(url) => open_url(url),
+                    None => println!("{} has no URL.", todo.name),
+                }
+            }
+        }
+    }
+    Ok(Outcome::Done)
+}
+
+/// 与 view 在非终端环境下的输出相同，但无论是否在终端中都不进入交互
+fn cmd_list(
+    todo_list: &TodoList,
+    options: &ViewOptions,
+    json: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let todos = filter_items(todo_list.analysis(), options);
+    if json {
+        print_json(&todos)?;
+        return Ok(Outcome::found(!todos.is_empty()));
+    }
+    if todos.is_empty() {
+        info!("No item in history.");
+        return Ok(Outcome::NotFound);
+    }
+    let (todos, labels) = tree_labels(&todos, false);
+    print_plain_list(&todos, &labels);
+    Ok(Outcome::Done)
+}
+
+/// 按 view 的选项筛选、排序并截断
+fn filter_items(items: &[TodoItem], options: &ViewOptions) -> Vec<TodoItem> {
+    let mut todos = items.to_vec();
+    if options.overdue {
+        // 按本地时区的日期比较，没有截止日期的项不算逾期
+        let today = Local::now().date_naive();
+        todos.retain(|todo| todo.is_overdue(today));
+    }
+    todos.retain(|todo| todo.has_all_tags(&options.tags));
+    todos.retain(|todo| options.due.contains(todo));
+    // --priority-min 为负数时显然是要看 backlog，不必再加 --include-backlog
+    let include_backlog = options.include_backlog || options.priority_min.is_some_and(is_backlog);
+    todos.retain(|todo| include_backlog || !is_backlog(todo.priority));
+    todos.retain(|todo| {
+        options.priority_min.is_none_or(|min| todo.priority >= min)
+            && options.priority_max.is_none_or(|max| todo.priority <= max)
+    });
+    sort_items(&mut todos, options.sort, options.reverse);
+    if options.limit > 0 {
+        todos.truncate(options.limit);
+    }
+    todos
+}
+
+/// 子任务缩进显示在父任务下方，返回按显示顺序排列的条目和对应的标签；
+/// 未完成且有截止日期的项在标签后附上相对日期，便于扫一眼找出紧急的项；
+/// badges 为 true 时（交互选择列表）优先级改为行尾带颜色的标记
+fn tree_labels(todos: &[TodoItem], badges: bool) -> (Vec<TodoItem>, Vec<String>) {
+    let today = Local::now().date_naive();
+    build_tree(todos)
+        .into_iter()
+        .map(|(depth, todo)| {
+            let summary = if badges {
+                format!("{} (ID: {})", todo.name, todo.id)
+            } else {
+                todo.summary()
+            };
+            let mut label = if depth == 0 {
+                summary
+            } else {
+                format!("{}└ {}", "  ".repeat(depth - 1), summary)
+            };
+            if let Some(due) = todo.due
+                && !todo.completed
+            {
+                label.push_str(&format!(" - {}", humanize_due(due, today)));
+            }
+            if badges {
+                label.push(' ');
+                label.push_str(&priority_badge(todo.priority));
+            }
+            (todo.clone(), label)
+        })
+        .unzip()
+}
+
+fn print_plain_list(todos: &[TodoItem], labels: &[String]) {
+    for (todo, label) in todos.iter().zip(labels) {
+        let mark = if todo.completed { "[x]" } else { "[ ]" };
+        println!("{} {}", mark, label);
+    }
+}
+
+/// 按截止日期逐日打印，每天一个标题；组内保持传入的顺序
+fn print_grouped_by_day(todos: &[TodoItem]) {
+    let mut by_day: BTreeMap<NaiveDate, Vec<&TodoItem>> = BTreeMap::new();
+    let mut no_due = Vec::new();
+    for todo in todos {
+        match todo.due {
+            Some(due) => by_day.entry(due).or_default().push(todo),
+            None => no_due.push(todo),
+        }
+    }
+    let today = Local::now().date_naive();
+    let groups = by_day
+        .into_iter()
+        .map(|(day, items)| {
+            let header = format!(
+                "{} ({})",
+                day.format("%Y-%m-%d %a"),
+                humanize_due(day, today)
+            );
+            (header, items)
+        })
+        .chain((!no_due.is_empty()).then(|| ("No due date".to_string(), no_due)));
+    for (index, (header, items)) in groups.enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!(
+            "{}",
+            header.if_supports_color(Stream::Stdout, |header| header.bold())
+        );
+        for todo in items {
+            let mark = if todo.completed { "[x]" } else { "[ ]" };
+            println!("  {} {}", mark, todo.summary());
+        }
+    }
+}
+
+/// 连续写入（如批量添加）在这段时间内只触发一次刷新
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+fn cmd_watch(path: String, options: &ViewOptions, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let file = PathBuf::from(&path);
+    let file_name = file.file_name().map(|name| name.to_os_string());
+    // 保存时先写临时文件再改名替换，监视文件本身会在第一次保存后失效，因此监视所在目录
+    let dir = match file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .map_err(|e| format!("Failed to watch {}: {}", path, e))?;
+    watcher
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| format!("Failed to watch {}: {}", dir.display(), e))?;
+
+    let is_target = |event: &notify::Result<notify::Event>| {
+        // 自己读取文件也会产生访问事件，只关心内容变化
+        event.as_ref().is_ok_and(|event| {
+            !event.kind.is_access()
+                && event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == file_name.as_deref())
+        })
+    };
+    loop {
+        render_watch(&path, options, json);
+        // 等到目标文件变化，再把静默期内的后续事件一并丢弃
+        loop {
+            let event = receiver
+                .recv()
+                .map_err(|_| format!("Stopped watching {}.", path))?;
+            if is_target(&event) {
+                break;
+            }
+        }
+        while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    }
+}
+
+/// 重新读取文件并打印一次；读取失败（如正在被手工编辑）只提示，继续监视
+fn render_watch(path: &str, options: &ViewOptions, json: bool) {
+    let todo_list = match TodoList::snapshot(path) {
+        Ok(todo_list) => todo_list,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+    let todos = filter_items(todo_list.analysis(), options);
+    if json {
+        // 每次变化输出一行 JSON，便于其他程序逐行读取
+        if let Err(e) = print_json(&todos) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+    if io::stdout().is_terminal() {
+        let _ = io::stdout()
+            .execute(Clear(ClearType::All))
+            .and_then(|stdout| stdout.execute(MoveTo(0, 0)));
+    }
+    info!(
+        "{} (updated {}, Ctrl-C to quit)",
+        path,
+        Local::now().format("%H:%M:%S")
+    );
+    if let Some(GroupBy::Day) = options.group_by {
+        print_grouped_by_day(&todos);
+        return;
+    }
+    let (todos, labels) = tree_labels(&todos, false);
+    print_plain_list(&todos, &labels);
+}
+
+fn find_matches(
+    todo_list: &TodoList,
+    name: &str,
+    mode: FindMode,
+) -> Result<Vec<TodoItem>, Box<dyn Error>> {
+    let found = match mode {
+        FindMode::Fuzzy => {
+            let ranked = todo_list.fuzzy_find(name);
+            ranked.into_iter().map(|(item, _)| item).collect()
+        }
+        FindMode::Regex => todo_list
+            .find_items_by_regex(name)
+            .map_err(|e| format!("Invalid regular expression: {}", e))?,
+        FindMode::AllFields => todo_list.find_items(name),
+        FindMode::Content => todo_list.find_items_by_content(name),
+        FindMode::NameCased => todo_list.find_items_by_name_cased(name),
+        FindMode::Name => todo_list.find_items_by_name(name),
+    };
+    Ok(found.into_iter().cloned().collect())
+}
+
+/// found 为 find_matches（或索引查找）的结果，按 find 的选项筛选后输出
+fn cmd_find(
+    mut found: Vec<TodoItem>,
+    mode: FindMode,
+    due: DueRange,
+    limit: usize,
+    field: Option<Field>,
+    json: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    found.retain(|item| due.contains(item));
+    if limit > 0 {
+        // 模糊匹配的结果已按相似度排好
+        if mode != FindMode::Fuzzy {
+            found.sort_by_key(|item| std::cmp::Reverse(item.priority_key()));
+        }
+        found.truncate(limit);
+    }
+    if json {
+        print_json(&found)?;
+        return Ok(Outcome::found(!found.is_empty()));
+    }
+    if let Some(field) = field {
+        return Ok(print_field(&found, field));
+    }
+    if found.is_empty() {
+        info!("No item with that name found");
+        return Ok(Outcome::NotFound);
+    }
+    found.iter().for_each(|x| {
+        println!("--------------------\n{}\n--------------------", x);
+    });
+    Ok(Outcome::Done)
+}
+
+fn cmd_clear(path: String) -> Result<Outcome, Box<dyn Error>> {
+    if !DRY_RUN.load(Ordering::Relaxed) && !confirm_or_cancel("Are you sure?")? {
+        info!("Canceled.");
+        return Ok(Outcome::Done);
+    }
+    let mut todo_list = open_todo_list(path)?;
+    let removed = todo_list
+        .clear()
+        .map_err(|e| format!("There is something wrong. {}", e))?;
+    todo_list.commit()?;
+    info!("Cleared {} items.", removed);
+    if removed > 0 && !DRY_RUN.load(Ordering::Relaxed) {
+        info!("Run `todo undo` to bring them back.");
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_delete_by_id(todo_list: &mut TodoList, id: u64) -> Result<Outcome, Box<dyn Error>> {
+    let Some(item) = todo_list
+        .analysis()
+        .iter()
+        .find(|item| item.id == id)
+        .cloned()
+    else {
+        info!("No item with id {}.", id);
+        return Ok(Outcome::NotFound);
+    };
+    info!("--------------------\n{}\n--------------------", item);
+    delete_item(todo_list, &item);
+    info!("Deleted item {}.", id);
+    Ok(Outcome::Done)
+}
+
+fn cmd_delete(
+    todo_list: &mut TodoList,
+    name: &str,
+    exact: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    let found = if exact {
+        todo_list.find_items_by_exact_name(name)
+    } else {
+        todo_list.find_items_by_name(name)
+    };
+    let todos: Vec<TodoItem> = found
+        .into_iter()
+        .cloned() // 克隆 TodoItem，脱离对 todo_list 的借用
+        .collect();
+
+    if todos.is_empty() {
+        if exact {
+            info!("No item named exactly {}.", name);
+        } else {
+            info!("No item named {}.", name);
+        }
+        return Ok(Outcome::NotFound);
+    }
+    // 精确匹配只有一项时不必再选择
+    if exact && todos.len() == 1 {
+        info!("--------------------\n{}\n--------------------", todos[0]);
+        if delete_item(todo_list, &todos[0]) {
+            info!("Deleted {}", todos[0].name);
+        }
+        return Ok(Outcome::Done);
+    }
+
+    // 空格勾选多个匹配项，Enter 一次性删除所有勾选项
+    let labels: Vec<String> = todos.iter().map(badge_label).collect();
+    match multi_select(&labels, visible_rows()) {
+        Ok(Some(selected)) => {
+            for todo in selected.into_iter().map(|i| &todos[i]) {
+                info!("--------------------\n{}\n--------------------", todo);
+                if delete_item(todo_list, todo) {
+                    info!("Deleted {}", todo.name);
+                } else {
+                    info!("No item named {}", todo.name);
+                }
+            }
+        }
+        Ok(None) => info!("Delete canceled."),
+        Err(e) => return Err(format!("Error during selection: {}", e).into()),
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_delete_batch(
+    todo_list: &mut TodoList,
+    batch: BatchDelete,
+) -> Result<Outcome, Box<dyn Error>> {
+    let affected: Vec<String> = todo_list
+        .analysis()
+        .iter()
+        .filter(|item| batch.matches(item))
+        .map(TodoItem::summary)
+        .collect();
+    if affected.is_empty() {
+        info!("No item with {}.", batch);
+        return Ok(Outcome::NotFound);
+    }
+    // 先列出会被删除的所有项，避免误删比预期更多的内容
+    info!("{} items with {}:", affected.len(), batch);
+    for summary in &affected {
+        info!("  {}", summary);
+    }
+    if !DRY_RUN.load(Ordering::Relaxed)
+        && !confirm_or_cancel(&format!(
+            "Delete these {} items. Are you sure?",
+            affected.len()
+        ))?
+    {
+        info!("Canceled.");
+        return Ok(Outcome::Done);
+    }
+    let removed = match batch {
+        BatchDelete::Tag(tag) => todo_list.del_by_tag(&tag),
+        BatchDelete::Priority(priority) => todo_list.del_by_priority(priority),
+        BatchDelete::Name(keyword) => todo_list.del_by_keyword(&keyword),
+    };
+    info!("Deleted {} items.", removed);
+    Ok(Outcome::Done)
+}
+
+fn cmd_done(todo_list: &mut TodoList, name: &str) -> Result<Outcome, Box<dyn Error>> {
+    let Some(item) = todo_list.pending_by_name(name).cloned() else {
+        info!("No pending item named {}.", name);
+        return Ok(Outcome::NotFound);
+    };
+    complete_item(todo_list, &item)?;
+    Ok(Outcome::Done)
+}
+
+// 多个匹配项时由下拉菜单的回调修改列表，因此这里自行打开并包进 Arc<Mutex>
+fn cmd_edit(path: String, name: String, changes: ItemChanges) -> Result<Outcome, Box<dyn Error>> {
+    if changes.is_empty() {
+        info!("Nothing to change.");
+        return Ok(Outcome::Done);
+    }
+
+    let todo_list = Arc::new(Mutex::new(open_todo_list(path)?));
+    // 优先精确匹配名称，找不到再退回到关键词匹配
+    let todos: Vec<TodoItem> = {
+        let list_guard = todo_list.lock().unwrap();
+        let exact: Vec<TodoItem> = list_guard
+            .analysis()
+            .iter()
+            .filter(|item| item.name == name)
+            .cloned()
+            .collect();
+        if exact.is_empty() {
+            list_guard
+                .find_items_by_name(&name[..])
+                .into_iter()
+                .cloned()
+                .collect()
+        } else {
+            exact
+        }
+    };
+
+    if todos.is_empty() {
+        info!("No item with that name found.");
+        return Ok(Outcome::NotFound);
+    }
+    if todos.len() == 1 {
+        if edit_selected(&mut todo_list.lock().unwrap(), &todos[0], changes)? {
+            info!("Updated item: {}", todos[0].name);
+        }
+        return commit_shared(todo_list).map(|()| Outcome::Done);
+    }
+
+    // 下拉菜单无法运行的终端中改为输入编号选择
+    if !terminal_capable() {
+        return edit_numbered(todo_list, &todos, changes);
+    }
+
+    // 多个匹配项时复用下拉菜单，由用户选择要编辑的那一项；回调无法返回错误，记下后在菜单结束时返回
+    let failure: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(None));
+    let mut drop_down_items = HashMap::new();
+    for todo in todos.iter().cloned() {
+        let list_clone = todo_list.clone();
+        let failure = failure.clone();
+        let changes = changes.clone();
+        drop_down_items.insert(
+            DropDownEntry(todo.clone()),
+            move |_selected: &DropDownEntry| {
+                let mut list_guard = list_clone.lock().unwrap();
+                match edit_selected(&mut list_guard, &todo, changes) {
+                    Ok(true) => println!("\nSuccessfully updated item: {}", todo.name),
+                    Ok(false) => {}
+                    Err(e) => *failure.lock().unwrap() = Some(e.to_string()),
+                }
+            },
+        );
+    }
+
+    println!(
+        "Found {} matching items. Use Up/Down to select, Enter to edit, Esc to cancel.",
+        drop_down_items.len()
+    );
+    let rows = visible_rows().min(drop_down_items.len());
+    let dropdown = TerminalDropDown::use_drop_down(drop_down_items, rows);
+    if let Err(e) = dropdown.wait() {
+        eprintln!("Error during selection: {:?}", e);
+        return edit_numbered(todo_list, &todos, changes);
+    }
+    commit_shared(todo_list)?;
+    match failure.lock().unwrap().take() {
+        Some(e) => Err(e.into()),
+        None => Ok(Outcome::Done),
+    }
+}
+
+fn edit_numbered(
+    todo_list: Arc<Mutex<TodoList>>,
+    todos: &[TodoItem],
+    changes: ItemChanges,
+) -> Result<Outcome, Box<dyn Error>> {
+    let labels: Vec<String> = todos.iter().map(TodoItem::summary).collect();
+    let Some(selected) = numbered_select(&labels, false) else {
+        info!("Canceled selection.");
+        return Ok(Outcome::Done);
+    };
+    let todo = &todos[selected[0]];
+    if edit_selected(&mut todo_list.lock().unwrap(), todo, changes)? {
+        info!("Updated item: {}", todo.name);
+    }
+    commit_shared(todo_list).map(|()| Outcome::Done)
+}
+
+/// 修改选中的那一项；新名称与另一项重名（忽略大小写）时不做修改并返回错误，只改大小写允许
+fn edit_selected(
+    todo_list: &mut TodoList,
+    todo: &TodoItem,
+    changes: ItemChanges,
+) -> Result<bool, Box<dyn Error>> {
+    if let Some(new_name) = &changes.name
+        && let Some(conflict) = todo_list.name_conflict(new_name)
+        && conflict.id != todo.id
+    {
+        let e = AddError::Duplicate(conflict.name.clone());
+        return Err(format!("Cannot edit {}: {}", todo.name, e).into());
+    }
+    Ok(todo_list.edit_item(todo.id, changes))
+}
+
+/// 下拉菜单结束后回调已全部释放，收回列表的唯一所有权再保存；
+/// 万一仍被引用（菜单线程异常），退回到 Drop 时保存
+fn commit_shared(todo_list: Arc<Mutex<TodoList>>) -> Result<(), Box<dyn Error>> {
+    match Arc::try_unwrap(todo_list) {
+        Ok(todo_list) => todo_list
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner())
+            .commit(),
+        Err(_) => Ok(()),
+    }
+}
+
+fn cmd_rename(todo_list: &mut TodoList, old: &str, new: &str) -> Result<Outcome, Box<dyn Error>> {
+    let candidates: Vec<TodoItem> = todo_list.resolve_items(old).into_iter().cloned().collect();
+    let item = match candidates.len() {
+        0 => {
+            info!("No item named {}.", old);
+            return Ok(Outcome::NotFound);
+        }
+        1 => &candidates[0],
+        n if !io::stdin().is_terminal() || !io::stdout().is_terminal() => {
+            return Err(format!(
+                "'{}' matches {} items; use a more specific name or an interactive terminal.",
+                old, n
+            )
+            .into());
+        }
+        _ => {
+            let labels: Vec<String> = candidates.iter().map(TodoItem::summary).collect();
+            match single_select(&labels, visible_rows()) {
+                Ok(Some(i)) => &candidates[i],
+                Ok(None) => {
+                    info!("Canceled selection.");
+                    return Ok(Outcome::Done);
+                }
+                Err(e) => return Err(format!("Error during selection: {}", e).into()),
+            }
+        }
+    };
+    todo_list.rename(item.id, new)?;
+    info!("Renamed {} to {}.", item.name, new);
+    Ok(Outcome::Done)
+}
+
+fn cmd_open(todo_list: &TodoList, name: &str) -> Result<Outcome, Box<dyn Error>> {
+    let candidates: Vec<TodoItem> = todo_list
+        .resolve_items(name)
+        .into_iter()
+        .filter(|item| item.url.is_some())
+        .cloned()
+        .collect();
+    if candidates.is_empty() {
+        info!("No item named {} has a URL.", name);
+        return Ok(Outcome::NotFound);
+    }
+    for item in pick_items(candidates) {
+        open_url(item.url.as_deref().unwrap_or_default());
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_copy(todo_list: &TodoList, name: &str) -> Result<Outcome, Box<dyn Error>> {
+    let candidates: Vec<TodoItem> = todo_list.resolve_items(name).into_iter().cloned().collect();
+    if candidates.is_empty() {
+        info!("No item named {}.", name);
+        return Ok(Outcome::NotFound);
+    }
+    let picked = pick_items(candidates);
+    if picked.is_empty() {
+        return Ok(Outcome::Done);
+    }
+    let contents: Vec<&str> = picked.iter().map(|item| item.content.as_str()).collect();
+    copy_to_clipboard(&contents.join("\n\n"));
+    Ok(Outcome::Done)
+}
+
+fn cmd_export(
+    todo_list: &TodoList,
+    format: ExportFormat,
+    output: Option<&Path>,
+) -> Result<Outcome, Box<dyn Error>> {
+    let mut todos = todo_list.analysis().to_vec();
+    sort_items(&mut todos, SortField::Priority, false);
+    let rendered = match format {
+        ExportFormat::Markdown => to_markdown(&todos),
+        ExportFormat::Csv => to_csv(&todos).map_err(|e| format!("Failed to export CSV: {}", e))?,
+        ExportFormat::Taskwarrior => to_taskwarrior(&todos)
+            .map_err(|e| format!("Failed to export Taskwarrior JSON: {}", e))?,
+    };
+    match output {
+        Some(output) => {
+            fs::write(output, rendered)
+                .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+            info!("Exported {} items to {}.", todos.len(), output.display());
+        }
+        None => print!("{}", rendered),
+    }
+    Ok(Outcome::Done)
+}
+
+/// 在单独的线程中读取并解析一个源文件；错误转成字符串以便跨线程返回
+fn parse_import_file(
+    from: &Path,
+    format: ImportFormat,
+    include_closed: bool,
+) -> Result<Vec<TodoItem>, String> {
+    let source =
+        fs::File::open(from).map_err(|e| format!("Failed to open {}: {}", from.display(), e))?;
+    match format {
+        ImportFormat::Csv => from_csv(source),
+        ImportFormat::Github => {
+            from_github_issues(BufReader::new(source), default_priority(), include_closed)
+        }
+        ImportFormat::Taskwarrior => from_taskwarrior(BufReader::new(source), default_priority()),
+    }
+    .map_err(|e| format!("Failed to parse {}: {}", from.display(), e))
+}
+
+fn cmd_import(
+    path: String,
+    format: ImportFormat,
+    from: Vec<PathBuf>,
+    include_closed: bool,
+) -> Result<Outcome, Box<dyn Error>> {
+    // 每个文件一个线程并行解析；任一文件出错则整体放弃，不改动待办文件
+    let mut scope = JoinHandlerScope::new();
+    for source in from {
+        scope.add(std::thread::spawn(move || {
+            parse_import_file(&source, format, include_closed)
+        }));
+    }
+    let mut items = Vec::new();
+    for parsed in scope.join() {
+        items.extend(parsed?);
+    }
+
+    let mut todo_list = open_todo_list(path)?;
+    let (mut imported, mut skipped, mut invalid) = (0, 0, 0);
+    for item in items {
+        let name = item.name.clone();
+        match todo_list.add_item(item) {
+            Ok(()) => imported += 1,
+            Err(AddError::Duplicate(_)) => skipped += 1,
+            Err(e) => {
+                info!("Skipped {:?}: {}", name, e);
+                invalid += 1;
+            }
+        }
+    }
+    if invalid > 0 {
+        info!(
+            "Imported {} items, skipped {} duplicates and {} invalid rows.",
+            imported, skipped, invalid
+        );
+    } else {
+        info!(
+            "Imported {} items, skipped {} duplicates.",
+            imported, skipped
+        );
+    }
+    todo_list.commit()?;
+    Ok(Outcome::Done)
+}
+
+// 计数不需要加载完整的列表，文件不存在时视为 0
+fn cmd_count(path: String, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let count = match fs::File::open(&path) {
+        Ok(file) if is_toml_path(Path::new(&path)) => TomlStorage
+            .deserialize(&mut BufReader::new(file))
+            .map_err(|e| CorruptFile::new(Path::new(&path), e))?
+            .iter()
+            .filter(|item| !item.completed)
+            .count(),
+        Ok(file) => count_pending(BufReader::new(file))
+            .map_err(|e| CorruptFile::new(Path::new(&path), e))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(format!("Failed to open {}: {}", path, e).into()),
+    };
+    if json {
+        print_json(&serde_json::json!({ "count": count }))?;
+    } else {
+        println!("{}", count);
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_stats(todo_list: &TodoList, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let stats = compute_stats(todo_list.analysis());
+    if json {
+        print_json(&stats)?;
+    } else {
+        print!("{}", stats);
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_today(todo_list: &TodoList, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let mut todos: Vec<TodoItem> = todo_list
+        .analysis()
+        .iter()
+        .filter(|item| {
+            !item.completed && (item.due == Some(today) || item.level() >= Priority::High)
+        })
+        .cloned()
+        .collect();
+    sort_items(&mut todos, SortField::Priority, false);
+    if json {
+        print_json(&todos)?;
+        return Ok(Outcome::found(!todos.is_empty()));
+    }
+    if todos.is_empty() {
+        info!("Nothing due today.");
+        return Ok(Outcome::NotFound);
+    }
+    for todo in &todos {
+        println!("--------------------\n{}\n--------------------", todo);
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_move(from: String, to: String, name: &str) -> Result<Outcome, Box<dyn Error>> {
+    if from == to {
+        info!("Warning: source and destination are the same file, nothing to move.");
+        return Ok(Outcome::Done);
+    }
+    let mut from_list = TodoList::open(&from)?;
+    let mut to_list = TodoList::open(&to)?;
+    remember_path(&from);
+
+    let candidates: Vec<TodoItem> = from_list.resolve_items(name).into_iter().cloned().collect();
+    if candidates.is_empty() {
+        info!("No item named {}.", name);
+        return Ok(Outcome::NotFound);
+    }
+
+    // 先加入目标列表，重名的跳过，源列表保持不变
+    let mut moved = Vec::new();
+    for (item, result) in to_list.add_copied(pick_items(candidates), true) {
+        match result {
+            Ok(()) => moved.push(item),
+            Err(AddError::Duplicate(_)) => info!(
+                "Skipped {}: {} already has an item with that name.",
+                item.name, to
+            ),
+            Err(e) => info!("Skipped {}: {}", item.name, e),
+        }
+    }
+    if moved.is_empty() {
+        return Ok(Outcome::Done);
+    }
+
+    // 目标文件写入成功后才从源列表移除，保证任何时刻条目都不会丢失
+    to_list.commit()?;
+    // 按 id 移除：重复任务完成后可能有同名的多项，按名称会删错
+    for item in &moved {
+        from_list.remove_by_id(item.id);
+        info!("Moved {} to {}", item.name, to);
+    }
+    from_list.commit()?;
+    Ok(Outcome::Done)
+}
+
+fn cmd_merge(into: String, from: String) -> Result<Outcome, Box<dyn Error>> {
+    if into == from {
+        info!("Warning: both paths point to {}, nothing to merge.", into);
+        return Ok(Outcome::Done);
+    }
+    // 源文件只读打开，合并过程中不会被修改
+    let from_list = open_todo_list_read_only(from.clone())?;
+    if from_list.analysis().is_empty() {
+        info!("{} is empty, nothing to merge.", from);
+        return Ok(Outcome::Done);
+    }
+    let mut into_list = TodoList::open(&into)?;
+    remember_path(&into);
+    let (mut added, mut skipped) = (0, 0);
+    for (item, result) in into_list.add_copied(from_list.analysis().clone(), true) {
+        match result {
+            Ok(()) => added += 1,
+            Err(e) => {
+                // 重名是合并时的常见情况，只有其他原因才单独说明
+                if !matches!(e, AddError::Duplicate(_)) {
+                    info!("Skipped {}: {}", item.name, e);
+                }
+                skipped += 1;
+            }
+        }
+    }
+    into_list.commit()?;
+    info!(
+        "Merged {} into {}: {} added, {} skipped.",
+        from, into, added, skipped
+    );
+    Ok(Outcome::Done)
+}
+
+fn cmd_archive(path: String, archive_path: String) -> Result<Outcome, Box<dyn Error>> {
+    let mut todo_list = TodoList::open(&path)?;
+    remember_path(&path);
+    let completed: Vec<TodoItem> = todo_list
+        .analysis()
+        .iter()
+        .filter(|item| item.completed)
+        .cloned()
+        .collect();
+    if completed.is_empty() {
+        info!("No completed items to archive.");
+        return Ok(Outcome::Done);
+    }
+
+    // 归档文件写入成功后才从主列表移除
+    let mut archive_list = TodoList::open(&archive_path)?;
+    archive_list.add_copied(completed, false);
+    archive_list.commit()?;
+    let archived = todo_list.remove_completed();
+    todo_list.commit()?;
+    info!("Archived {} items to {}.", archived, archive_path);
+    Ok(Outcome::Done)
+}
+
+fn cmd_dedup(todo_list: &mut TodoList, by: DedupKey) -> Result<Outcome, Box<dyn Error>> {
+    let removed = todo_list.dedup(by);
+    info!("Removed {} duplicate items.", removed);
+    Ok(Outcome::Done)
+}
+
+fn cmd_repair(path: String) -> Result<Outcome, Box<dyn Error>> {
+    if is_toml_path(Path::new(&path)) {
+        return Err("repair only supports JSON todo files.".into());
+    }
+    let lock = lock_file(Path::new(&path))?;
+    let text = fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let (items, dropped) = repair_items(&text);
+    let recovered = items.len();
+    TodoList::with_items(items, &path, Some(lock)).commit()?;
+    info!(
+        "Recovered {} items, dropped {} damaged entries.",
+        recovered, dropped
+    );
+    if dropped > 0 && config().backups.unwrap_or(DEFAULT_BACKUPS) > 0 {
+        info!("The damaged file was backed up; run `todo undo` to go back to it.");
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_restore(path: String, name: &str) -> Result<Outcome, Box<dyn Error>> {
+    let trash_path = trash_path(Path::new(&path));
+    if !trash_path.exists() {
+        info!("The trash is empty.");
+        return Ok(Outcome::NotFound);
+    }
+    let mut trash = TodoList::open(&trash_path.to_string_lossy())?;
+    let candidates: Vec<TodoItem> = trash.resolve_items(name).into_iter().cloned().collect();
+    if candidates.is_empty() {
+        info!("No item named {} in the trash.", name);
+        return Ok(Outcome::NotFound);
+    }
+    let picked = pick_items(candidates);
+    if picked.is_empty() {
+        return Ok(Outcome::Done);
+    }
+
+    let mut todo_list = open_todo_list(path)?;
+    let mut restored = Vec::new();
+    for (item, result) in todo_list.add_copied(picked, true) {
+        match result {
+            Ok(()) => {
+                info!("Restored {}", item.name);
+                restored.push(item.id);
+            }
+            Err(e) => info!("Skipped {}: {}", item.name, e),
+        }
+    }
+    // 列表保存成功后才从回收站移除，保证任何时刻条目都不会丢失
+    todo_list.commit()?;
+    for id in restored {
+        trash.remove_by_id(id);
+    }
+    trash.commit()?;
+    Ok(Outcome::Done)
+}
+
+fn cmd_empty_trash(path: String) -> Result<Outcome, Box<dyn Error>> {
+    let trash_path = trash_path(Path::new(&path));
+    if !trash_path.exists() {
+        info!("The trash is empty.");
+        return Ok(Outcome::Done);
+    }
+    // 打开只为加锁和计数，删除文件前一直持有锁
+    let mut trash = TodoList::open(&trash_path.to_string_lossy())?;
+    trash.detach();
+    let count = trash.analysis().len();
+    if DRY_RUN.load(Ordering::Relaxed) {
+        info!(
+            "Dry run: would permanently delete {} items in {}.",
+            count,
+            trash_path.display()
+        );
+        return Ok(Outcome::Done);
+    }
+    if !confirm_or_cancel(&format!("Permanently delete {} items in the trash?", count))? {
+        info!("Canceled.");
+        return Ok(Outcome::Done);
+    }
+    // 回收站的备份也一并删除，清空后无法再用 undo 找回
+    remove_with_backups(&trash_path)
+        .map_err(|e| format!("Failed to remove {}: {}", trash_path.display(), e))?;
+    info!("Emptied the trash ({} items).", count);
+    Ok(Outcome::Done)
+}
+
+fn cmd_undo(path: String) -> Result<Outcome, Box<dyn Error>> {
+    if DRY_RUN.load(Ordering::Relaxed) {
+        info!("Dry run: would restore {} from its latest backup.", path);
+        return Ok(Outcome::Done);
+    }
+    // 与其他修改文件的命令互斥，恢复期间不会有人写入
+    let _lock = lock_file(Path::new(&path))?;
+    match restore_backup(Path::new(&path)) {
+        Ok(true) => info!("Restored {} from backup.", path),
+        Ok(false) => {
+            info!("No backup to restore for {}.", path);
+            return Ok(Outcome::NotFound);
+        }
+        Err(e) => return Err(format!("Failed to restore {}: {}", path, e).into()),
+    }
+    Ok(Outcome::Done)
+}
+
+fn cmd_reorder(todo_list: &mut TodoList) -> Result<Outcome, Box<dyn Error>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err("reorder needs an interactive terminal.".into());
+    }
+    // 从当前的手动顺序开始调整
+    let mut todos = todo_list.analysis().to_vec();
+    if todos.is_empty() {
+        info!("No item in history.");
+        return Ok(Outcome::NotFound);
+    }
+    sort_items(&mut todos, SortField::Manual, false);
+    let labels: Vec<String> = todos.iter().map(TodoItem::summary).collect();
+    let order = match reorder_items(&labels, visible_rows()) {
+        Ok(Some(order)) => order,
+        Ok(None) => {
+            info!("Reorder canceled.");
+            return Ok(Outcome::Done);
+        }
+        Err(e) => return Err(format!("Error during selection: {}", e).into()),
+    };
+    let ids: Vec<u64> = order.into_iter().map(|index| todos[index].id).collect();
+    todo_list.set_order(&ids);
+    info!("Saved the order of {} items.", ids.len());
+    Ok(Outcome::Done)
+}
+
+fn cmd_sync(
+    todo_list: &mut TodoList,
+    remote: String,
+    base: String,
+) -> Result<Outcome, Box<dyn Error>> {
+    let (remote_list, base_list) = (TodoList::snapshot(&remote)?, TodoList::snapshot(&base)?);
+    let merge = merge_three_way(
+        base_list.analysis(),
+        todo_list.analysis(),
+        remote_list.analysis(),
+    );
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    if !merge.conflicts.is_empty() && !interactive {
+        let names: Vec<&str> = merge.conflicts.iter().map(|c| c.name()).collect();
+        return Err(format!(
+            "{} conflicting item(s) need an interactive terminal to resolve: {}. Nothing was written.",
+            names.len(),
+            names.join(", ")
+        )
+        .into());
+    }
+
+    // 所有冲突都选好之后才写入，中途取消不改动本地文件
+    let mut items = merge.items;
+    for conflict in &merge.conflicts {
+        let describe = |item: &Option<TodoItem>| match item {
+            Some(item) => item.summary(),
+            None => "(deleted)".to_string(),
+        };
+        let labels = vec![
+            format!("Keep local:  {}", describe(&conflict.local)),
+            format!("Take remote: {}", describe(&conflict.remote)),
+        ];
+        let side = match single_select(&labels, visible_rows()) {
+            Ok(Some(0)) => SyncSide::Local,
+            Ok(Some(_)) => SyncSide::Remote,
+            Ok(None) => {
+                info!("Sync canceled; nothing was written.");
+                return Ok(Outcome::Done);
+            }
+            Err(e) => return Err(format!("Error during selection: {}", e).into()),
+        };
+        items.extend(conflict.resolve(side));
+    }
+    todo_list.replace_items(items);
+    info!(
+        "Synced from {}: {} added, {} removed, {} updated, {} conflict(s) resolved.",
+        remote,
+        merge.added,
+        merge.removed,
+        merge.updated,
+        merge.conflicts.len()
+    );
+    Ok(Outcome::Done)
+}
+
+fn cmd_diff(a: String, b: String, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let (list_a, list_b) = (TodoList::snapshot(&a)?, TodoList::snapshot(&b)?);
+    let diff = diff_items(list_a.analysis(), list_b.analysis());
+    if json {
+        print_json(&diff)?;
+        return Ok(Outcome::Done);
+    }
+    if diff.is_empty() {
+        info!("No differences.");
+        return Ok(Outcome::Done);
+    }
+    if !diff.only_in_a.is_empty() {
+        println!("Only in {}:", a);
+        for item in &diff.only_in_a {
+            println!("  - {}", item.summary());
+        }
+    }
+    if !diff.only_in_b.is_empty() {
+        println!("Only in {}:", b);
+        for item in &diff.only_in_b {
+            println!("  + {}", item.summary());
+        }
+    }
+    if !diff.changed.is_empty() {
+        println!("Changed:");
+        for changed in &diff.changed {
+            println!("  ~ {}: {}", changed.a.name, changed.fields.join(", "));
+            if changed.fields.contains(&"priority") {
+                println!(
+                    "      priority: {} -> {}",
+                    changed.a.priority, changed.b.priority
+                );
+            }
+            if changed.fields.contains(&"content") {
+                println!(
+                    "      content: {:?} -> {:?}",
+                    changed.a.content, changed.b.content
+                );
+            }
+        }
+    }
+    Ok(Outcome::Done)
+}
+
+#[derive(Serialize)]
+struct WhereInfo<'a> {
+    path: String,
+    source: &'a str, // --path、--profile、TODO_PATH、config 或 default
+    exists: bool,
+}
+
+fn cmd_where(path: String, source: &str, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let path =
+        std::path::absolute(&path).map_err(|e| format!("Failed to resolve {}: {}", path, e))?;
+    let info = WhereInfo {
+        path: path.to_string_lossy().into_owned(),
+        source,
+        exists: path.exists(),
+    };
+    if json {
+        print_json(&info)?;
+        return Ok(Outcome::Done);
+    }
+    println!("{}", info.path);
+    info!(
+        "(from {}{})",
+        info.source,
+        if info.exists {
+            ""
+        } else {
+            ", file does not exist yet"
+        }
+    );
+    Ok(Outcome::Done)
+}
+
+fn find_template(name: &str) -> Result<Template, Box<dyn Error>> {
+    config()
+        .templates
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("unknown template '{}' (see `todo template list`).", name).into())
+}
+
+/// 修改配置文件中的 [templates] 表：template 为 None 时删除。
+/// 用 toml_edit 改写，保留文件中的其他设置和注释；返回修改前是否已有同名模板
+fn write_template(name: &str, template: Option<&Template>) -> Result<bool, Box<dyn Error>> {
+    let path = config_path().ok_or("cannot determine the config directory.")?;
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Failed to read config {}: {}", path.display(), e).into()),
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+    let templates = document
+        .entry("templates")
+        .or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            toml_edit::Item::Table(table)
+        })
+        .as_table_mut()
+        .ok_or_else(|| format!("'templates' in {} is not a table.", path.display()))?;
+    let existed = match template {
+        Some(template) => {
+            let mut table = toml_edit::Table::new();
+            table.insert("content", toml_edit::value(template.content.as_str()));
+            if let Some(priority) = template.priority {
+                table.insert("priority", toml_edit::value(i64::from(priority)));
+            }
+            if !template.tags.is_empty() {
+                let tags: toml_edit::Array = template.tags.iter().collect();
+                table.insert("tags", toml_edit::value(tags));
+            }
+            templates
+                .insert(name, toml_edit::Item::Table(table))
+                .is_some()
+        }
+        None => templates.remove(name).is_some(),
+    };
+    if template.is_none() && !existed {
+        return Ok(false);
+    }
+    if DRY_RUN.load(Ordering::Relaxed) {
+        info!("Dry run: {} was not changed.", path.display());
+        return Ok(existed);
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, document.to_string())
+        .map_err(|e| format!("Failed to write config {}: {}", path.display(), e))?;
+    Ok(existed)
+}
+
+fn cmd_template(action: TemplateAction, json: bool) -> Result<Outcome, Box<dyn Error>> {
+    match action {
+        TemplateAction::Add {
+            name,
+            content,
+            priority,
+            tags,
+        } => {
+            let name = validate_name(&name)?;
+            let template = Template {
+                content: content.unwrap_or_default(),
+                priority,
+                tags: normalize_tags(tags),
+            };
+            let replaced = write_template(&name, Some(&template))?;
+            if replaced {
+                info!("Updated template \"{}\".", name);
+            } else {
+                info!("Added template \"{}\".", name);
+            }
+            Ok(Outcome::Done)
+        }
+        TemplateAction::List => {
+            let templates = &config().templates;
+            if json {
+                print_json(templates)?;
+                return Ok(Outcome::found(!templates.is_empty()));
+            }
+            if templates.is_empty() {
+                info!("No templates configured.");
+                return Ok(Outcome::NotFound);
+            }
+            for (name, template) in templates {
+                let mut line = format!("{}: {:?}", name, template.content);
+                if let Some(priority) = template.priority {
+                    line.push_str(&format!(" (priority {})", priority));
+                }
+                if !template.tags.is_empty() {
+                    line.push_str(&format!(" [{}]", template.tags.join(", ")));
+                }
+                println!("{}", line);
+            }
+            Ok(Outcome::Done)
+        }
+        TemplateAction::Delete { name } => {
+            if write_template(&name, None)? {
+                info!("Deleted template \"{}\".", name);
+                Ok(Outcome::Done)
+            } else {
+                info!("No template named \"{}\".", name);
+                Ok(Outcome::NotFound)
+            }
+        }
+    }
+}
+
+fn cmd_profiles(json: bool) -> Result<Outcome, Box<dyn Error>> {
+    let profiles: BTreeMap<&String, String> = config()
+        .profiles
+        .iter()
+        .map(|(name, path)| (name, expand_home(path)))
+        .collect();
+    if json {
+        print_json(&profiles)?;
+        return Ok(Outcome::found(!profiles.is_empty()));
+    }
+    if profiles.is_empty() {
+        info!("No profiles configured.");
+        return Ok(Outcome::NotFound);
+    }
+    for (name, path) in &profiles {
+        println!("{} -> {}", name, path);
+    }
+    Ok(Outcome::Done)
+}